@@ -0,0 +1,186 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, PathArguments, Type};
+
+/// Derive `rdq::queue::Item` for a struct with named fields, storing each
+/// field as its own stream field.
+///
+/// Plain fields are written with `ToString` and read back with `FromStr`.
+/// `Option` fields are omitted from the entry when `None`. Field attributes:
+///
+/// - `#[rdq(id)]` marks the `Option<String>` field that receives the entry id
+///   and is not written to the stream. Exactly one field must carry it, since
+///   acking and deleting an item needs its id.
+/// - `#[rdq(rename = "name")]` uses a different stream field name.
+/// - `#[rdq(json)]` stores the field as JSON, for nested serde types.
+#[proc_macro_derive(Item, attributes(rdq))]
+pub fn derive_item(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct Field {
+    ident: syn::Ident,
+    name: String,
+    id: bool,
+    json: bool,
+    optional: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "Item can only be derived for structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Item can only be derived for structs")),
+    };
+
+    let fields = fields.iter().map(parse_field).collect::<syn::Result<Vec<_>>>()?;
+
+    let mut ids = fields.iter().filter(|f| f.id);
+    let id = ids.next();
+    if let Some(extra) = ids.next() {
+        return Err(syn::Error::new_spanned(&extra.ident, "only one field can be marked `#[rdq(id)]`"));
+    }
+
+    let Some(id) = id else {
+        return Err(syn::Error::new_spanned(&input.ident, "`#[derive(Item)]` requires an `Option<String>` field marked `#[rdq(id)]`"));
+    };
+    let id_ident = &id.ident;
+
+    let decode = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let name = &f.name;
+
+        let value = match (f.id, f.json, f.optional) {
+            (true, _, _) => quote! { Some(stream_id.id.clone()) },
+            (false, false, false) => quote! { ::rdq::queue::item::derive::decode(stream_id, #name)? },
+            (false, false, true) => quote! { ::rdq::queue::item::derive::decode_optional(stream_id, #name)? },
+            (false, true, false) => quote! { ::rdq::queue::item::derive::decode_json(stream_id, #name)? },
+            (false, true, true) => quote! { ::rdq::queue::item::derive::decode_json_optional(stream_id, #name)? },
+        };
+
+        quote! { #ident: #value }
+    });
+
+    let encode = fields.iter().filter(|f| !f.id).map(|f| {
+        let ident = &f.ident;
+        let name = &f.name;
+
+        let value = if f.json {
+            quote! { ::rdq::queue::item::derive::encode_json(value)? }
+        } else {
+            quote! { ::rdq::queue::item::derive::encode(value) }
+        };
+
+        if f.optional {
+            quote! {
+                if let Some(value) = &self.#ident {
+                    fields.push((#name, #value));
+                }
+            }
+        } else {
+            quote! {
+                let value = &self.#ident;
+                fields.push((#name, #value));
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::rdq::queue::Item for #ident #ty_generics #where_clause {
+            fn id(&self) -> Option<&str> {
+                self.#id_ident.as_deref()
+            }
+
+            fn from_stream(
+                stream_id: &::rdq::queue::item::derive::StreamId
+            ) -> Result<Self, ::rdq::queue::Error> {
+                Ok(Self { #(#decode),* })
+            }
+
+            fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, ::rdq::queue::Error> {
+                #[allow(unused_mut)]
+                let mut fields = Vec::new();
+                #(#encode)*
+                Ok(fields)
+            }
+        }
+    })
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("named field");
+    let mut name = None;
+    let mut id = false;
+    let mut json = false;
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("rdq")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = true;
+            } else if meta.path.is_ident("json") {
+                json = true;
+            } else if meta.path.is_ident("rename") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error("unsupported rdq attribute, expected `id`, `json` or `rename`"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    if id && (json || name.is_some()) {
+        return Err(syn::Error::new_spanned(&ident, "`#[rdq(id)]` cannot be combined with `json` or `rename`"));
+    }
+
+    if id && !is_option_string(&field.ty) {
+        return Err(syn::Error::new_spanned(&field.ty, "`#[rdq(id)]` field must be an `Option<String>`"));
+    }
+
+    Ok(Field {
+        name: name.unwrap_or_else(|| ident.to_string()),
+        ident,
+        id,
+        json,
+        optional: is_option(&field.ty),
+    })
+}
+
+fn is_option(ty: &Type) -> bool {
+    option_argument(ty).is_some()
+}
+
+fn is_option_string(ty: &Type) -> bool {
+    let Some(Type::Path(path)) = option_argument(ty) else {
+        return false;
+    };
+
+    path.qself.is_none()
+        && path.path.segments.last().is_some_and(|s| s.ident == "String" && s.arguments.is_empty())
+}
+
+fn option_argument(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let segment = path.path.segments.last().filter(|s| s.ident == "Option")?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first()? {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
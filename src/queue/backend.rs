@@ -1,28 +1,96 @@
 pub mod combine;
+pub mod memory;
 pub mod stream;
 
+use crate::queue::delivery::Delivery;
 use crate::queue::error::Error;
 
 #[async_trait::async_trait]
 pub trait Backend<I> {
-    async fn enqueue(&mut self, item: &I) -> Result<(), Error>;
+    /// Identifies an enqueued item, e.g. its stream id.
+    type Id;
+
+    async fn enqueue(&mut self, item: &I) -> Result<Self::Id, Error>;
+    async fn enqueue_at(&mut self, item: &I, at: std::time::SystemTime) -> Result<(), Error>;
+    async fn enqueue_batch(&mut self, items: &[&I]) -> Result<Vec<Self::Id>, Error>;
     async fn dequeue(&mut self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<I>, Error>;
+    async fn dequeue_deliveries(&mut self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<Delivery<I>>, Error>;
     async fn ack(&mut self, items: &Vec<&I>) -> Result<(), Error>;
-    async fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem>, Error>;
+    async fn nack(&mut self, items: &[&I], requeue_delay: Option<std::time::Duration>) -> Result<(), Error>;
+    /// Give up on items, e.g. once retries are exhausted, moving them to the
+    /// backend's dead letter if it has one, and acking them.
+    async fn dead_letter(&mut self, items: &[&I]) -> Result<(), Error>;
+    async fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error>;
 }
 
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct DropOptions {
     pub min_idle_time: std::time::Duration,
+    /// Only drop items delivered at least this many times, counting the
+    /// deliveries before any nacks.
     pub max_deliveries: u64,
-    pub count: u64
+    /// Number of pending items inspected per round trip.
+    pub count: u64,
+    /// Stop after inspecting this many idle pending items, instead of
+    /// scanning all of them.
+    pub scan_limit: Option<u64>,
+    /// Only drop items pending for this consumer. Ignored by backends
+    /// without named consumers.
+    pub consumer: Option<String>,
+    /// Return the dropped entries in `DroppedItem::payload`.
+    pub payloads: bool
+}
+
+/// Drop everything pending, however idle, inspecting 100 items per round
+/// trip.
+impl Default for DropOptions {
+    fn default() -> Self {
+        Self {
+            min_idle_time: std::time::Duration::ZERO,
+            max_deliveries: 0,
+            count: 100,
+            scan_limit: None,
+            consumer: None,
+            payloads: false
+        }
+    }
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
-pub struct DroppedItem {
+pub struct DroppedItem<I> {
     pub id: String,
     pub idle: u64,
-    pub deliveries: u64
+    pub deliveries: u64,
+    /// The dropped entry, if `DropOptions::payloads` is set and it still existed.
+    pub payload: Option<DroppedPayload<I>>
+}
+
+#[derive(Debug)]
+pub enum DroppedPayload<I> {
+    Item(I),
+    /// The entry's raw fields, when they couldn't be parsed into an item.
+    Raw(std::collections::HashMap<String, redis::Value>)
+}
+
+impl<I> DroppedItem<I> {
+    pub fn map<J>(self, f: impl FnOnce(I) -> J) -> DroppedItem<J> {
+        DroppedItem {
+            id: self.id,
+            idle: self.idle,
+            deliveries: self.deliveries,
+            payload: self.payload.map(|p| match p {
+                DroppedPayload::Item(item) => DroppedPayload::Item(f(item)),
+                DroppedPayload::Raw(fields) => DroppedPayload::Raw(fields),
+            })
+        }
+    }
+}
+
+/// The time `delay` from now, failing if it's out of `SystemTime`'s range.
+pub(crate) fn due_in(delay: std::time::Duration) -> Result<std::time::SystemTime, Error> {
+    std::time::SystemTime::now()
+        .checked_add(delay)
+        .ok_or(Error::DelayError(delay))
 }
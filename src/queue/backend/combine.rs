@@ -1,6 +1,7 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use crate::queue::backend::{Backend, DropOptions, DroppedItem};
+use crate::queue::delivery::Delivery;
 use crate::queue::error::Error;
 
 #[derive(Clone)]
@@ -47,21 +48,21 @@ impl<I1, I2, B1: Backend<I1>, B2: Backend<I2>> Combine<I1, I2, B1, B2> {
         &mut self,
         n: usize,
         timeout: Option<Duration>,
-    ) -> Result<Vec<Either<I1, I2>>, Error> {
+    ) -> Result<Vec<Delivery<Either<I1, I2>>>, Error> {
         let res = match self.dequeue_stage {
             DequeueStage::Backend1 => self
                 .backend1
-                .dequeue(n, timeout)
+                .dequeue_deliveries(n, timeout)
                 .await?
                 .into_iter()
-                .map(Either::left)
+                .map(|d| d.map(Either::left))
                 .collect(),
             DequeueStage::Backend2 => self
                 .backend2
-                .dequeue(n, timeout)
+                .dequeue_deliveries(n, timeout)
                 .await?
                 .into_iter()
-                .map(Either::right)
+                .map(|d| d.map(Either::right))
                 .collect(),
         };
 
@@ -73,37 +74,37 @@ impl<I1, I2, B1: Backend<I1>, B2: Backend<I2>> Combine<I1, I2, B1, B2> {
         &mut self,
         n: usize,
         timeout: Option<Duration>,
-    ) -> Result<Vec<Either<I1, I2>>, Error> {
-        let items: Vec<Either<I1, I2>> = self
+    ) -> Result<Vec<Delivery<Either<I1, I2>>>, Error> {
+        let items: Vec<Delivery<Either<I1, I2>>> = self
             .backend1
-            .dequeue(n, None)
+            .dequeue_deliveries(n, None)
             .await?
             .into_iter()
-            .map(Either::left)
+            .map(|d| d.map(Either::left))
             .collect();
 
         if !items.is_empty() {
             return Ok(items);
         }
 
-        let items: Vec<Either<I1, I2>> = self
+        let items: Vec<Delivery<Either<I1, I2>>> = self
             .backend2
-            .dequeue(n, None)
+            .dequeue_deliveries(n, None)
             .await?
             .into_iter()
-            .map(Either::right)
+            .map(|d| d.map(Either::right))
             .collect();
 
         if !items.is_empty() {
             return Ok(items);
         }
 
-        let items: Vec<Either<I1, I2>> = self
+        let items: Vec<Delivery<Either<I1, I2>>> = self
             .backend1
-            .dequeue(n, timeout)
+            .dequeue_deliveries(n, timeout)
             .await?
             .into_iter()
-            .map(Either::left)
+            .map(|d| d.map(Either::left))
             .collect();
 
         Ok(items)
@@ -113,19 +114,71 @@ impl<I1, I2, B1: Backend<I1>, B2: Backend<I2>> Combine<I1, I2, B1, B2> {
 #[async_trait::async_trait]
 impl<I1: Send + Sync, I2: Send + Sync, B1: Backend<I1> + Send + Sync, B2: Backend<I2> + Send + Sync>
     Backend<Either<I1, I2>> for Combine<I1, I2, B1, B2>
+where
+    B1::Id: Send,
+    B2::Id: Send,
 {
-    async fn enqueue(&mut self, item: &Either<I1, I2>) -> Result<(), Error> {
+    /// Tagged with the backend the item was enqueued to.
+    type Id = Either<B1::Id, B2::Id>;
+
+    async fn enqueue(&mut self, item: &Either<I1, I2>) -> Result<Self::Id, Error> {
+        match item {
+            Either::Left(i) => self.backend1.enqueue(i).await.map(Either::Left),
+            Either::Right(i) => self.backend2.enqueue(i).await.map(Either::Right),
+        }
+    }
+
+    async fn enqueue_at(&mut self, item: &Either<I1, I2>, at: SystemTime) -> Result<(), Error> {
         match item {
-            Either::Left(i) => self.backend1.enqueue(i).await,
-            Either::Right(i) => self.backend2.enqueue(i).await,
+            Either::Left(i) => self.backend1.enqueue_at(i, at).await,
+            Either::Right(i) => self.backend2.enqueue_at(i, at).await,
         }
     }
 
+    /// Items are partitioned into one batch per backend, so a batch is not
+    /// atomic across the two.
+    async fn enqueue_batch(&mut self, items: &[&Either<I1, I2>]) -> Result<Vec<Self::Id>, Error> {
+        let i1: Vec<&I1> = items.iter().filter_map(|i| i.as_left()).collect();
+        let i2: Vec<&I2> = items.iter().filter_map(|i| i.as_right()).collect();
+
+        let ids1 = if i1.is_empty() {
+            vec![]
+        } else {
+            self.backend1.enqueue_batch(&i1).await?
+        };
+        let ids2 = if i2.is_empty() {
+            vec![]
+        } else {
+            self.backend2.enqueue_batch(&i2).await?
+        };
+        let (mut ids1, mut ids2) = (ids1.into_iter(), ids2.into_iter());
+
+        // Restore the original order of the items.
+        let ids = items
+            .iter()
+            .filter_map(|i| match i {
+                Either::Left(_) => ids1.next().map(Either::Left),
+                Either::Right(_) => ids2.next().map(Either::Right),
+            })
+            .collect();
+
+        Ok(ids)
+    }
+
     async fn dequeue(
         &mut self,
         n: usize,
         timeout: Option<Duration>,
     ) -> Result<Vec<Either<I1, I2>>, Error> {
+        let deliveries = self.dequeue_deliveries(n, timeout).await?;
+        Ok(deliveries.into_iter().map(|d| d.item).collect())
+    }
+
+    async fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<Either<I1, I2>>>, Error> {
         match self.dequeue_strategy {
             DequeueStrategy::RoundRobin => self.dequeue_round_robin(n, timeout).await,
             DequeueStrategy::Precedence => self.dequeue_precedence(n, timeout).await,
@@ -148,12 +201,48 @@ impl<I1: Send + Sync, I2: Send + Sync, B1: Backend<I1> + Send + Sync, B2: Backen
         Ok(())
     }
 
-    async fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem>, Error> {
+    async fn nack(
+        &mut self,
+        items: &[&Either<I1, I2>],
+        requeue_delay: Option<Duration>,
+    ) -> Result<(), Error> {
+        let i1: Vec<&I1> = items
+            .iter()
+            .filter_map(|i| Either::as_left(*i))
+            .collect();
+        let i2: Vec<&I2> = items
+            .iter()
+            .filter_map(|i| Either::as_right(*i))
+            .collect();
+
+        self.backend1.nack(&i1, requeue_delay).await?;
+        self.backend2.nack(&i2, requeue_delay).await?;
+
+        Ok(())
+    }
+
+    async fn dead_letter(&mut self, items: &[&Either<I1, I2>]) -> Result<(), Error> {
+        let i1: Vec<&I1> = items.iter().filter_map(|i| Either::as_left(*i)).collect();
+        let i2: Vec<&I2> = items.iter().filter_map(|i| Either::as_right(*i)).collect();
+
+        self.backend1.dead_letter(&i1).await?;
+        self.backend2.dead_letter(&i2).await?;
+
+        Ok(())
+    }
+
+    async fn drop_items(
+        &mut self,
+        options: &DropOptions,
+    ) -> Result<Vec<DroppedItem<Either<I1, I2>>>, Error> {
         let d1 = self.backend1.drop_items(options).await?;
-        let mut d2 = self.backend2.drop_items(options).await?;
+        let d2 = self.backend2.drop_items(options).await?;
 
-        let mut dropped = d1;
-        dropped.append(&mut d2);
+        let dropped = d1
+            .into_iter()
+            .map(|d| d.map(Either::Left))
+            .chain(d2.into_iter().map(|d| d.map(Either::Right)))
+            .collect();
 
         Ok(dropped)
     }
@@ -194,7 +283,7 @@ impl DequeueStage {
 
 #[cfg(test)]
 mod tests {
-    use crate::queue::{Backend, DroppedItem, Error, Item};
+    use crate::queue::{Backend, Delivery, DroppedItem, Error, Item};
     use std::sync::{Arc, Mutex};
 
     #[derive(Clone)]
@@ -222,22 +311,49 @@ mod tests {
 
     #[async_trait::async_trait]
     impl<I: Item + Clone + Send + Sync> Backend<I> for TestBackend<I> {
-        async fn enqueue(&mut self, item: &I) -> Result<(), Error> {
-            self.enqueued.lock().unwrap().push_back(item.clone());
+        type Id = usize;
 
-            Ok(())
+        async fn enqueue(&mut self, item: &I) -> Result<usize, Error> {
+            let mut enqueued = self.enqueued.lock().unwrap();
+            enqueued.push_back(item.clone());
+
+            Ok(enqueued.len() - 1)
+        }
+
+        async fn enqueue_at(&mut self, item: &I, _at: std::time::SystemTime) -> Result<(), Error> {
+            self.enqueue(item).await.map(|_| ())
+        }
+
+        async fn enqueue_batch(&mut self, items: &[&I]) -> Result<Vec<usize>, Error> {
+            let mut enqueued = self.enqueued.lock().unwrap();
+            let mut ids = vec![];
+            for item in items {
+                ids.push(enqueued.len());
+                enqueued.push_back((*item).clone());
+            }
+
+            Ok(ids)
         }
 
         async fn dequeue(
             &mut self,
             n: usize,
-            _timeout: Option<std::time::Duration>,
+            timeout: Option<std::time::Duration>,
         ) -> Result<Vec<I>, Error> {
+            let deliveries = self.dequeue_deliveries(n, timeout).await?;
+            Ok(deliveries.into_iter().map(|d| d.item).collect())
+        }
+
+        async fn dequeue_deliveries(
+            &mut self,
+            n: usize,
+            _timeout: Option<std::time::Duration>,
+        ) -> Result<Vec<Delivery<I>>, Error> {
             let mut res = vec![];
 
             for _ in 0..n {
                 if let Some(item) = self.enqueued.lock().unwrap().pop_front() {
-                    res.push(item);
+                    res.push(Delivery::new(item));
                 }
             }
 
@@ -251,10 +367,27 @@ mod tests {
             Ok(())
         }
 
+        async fn nack(
+            &mut self,
+            items: &[&I],
+            _requeue_delay: Option<std::time::Duration>,
+        ) -> Result<(), Error> {
+            let mut enqueued = self.enqueued.lock().unwrap();
+            for item in items {
+                enqueued.push_back((*item).clone());
+            }
+
+            Ok(())
+        }
+
+        async fn dead_letter(&mut self, items: &[&I]) -> Result<(), Error> {
+            self.ack(&items.to_vec()).await
+        }
+
         async fn drop_items(
             &mut self,
             _options: &crate::queue::backend::DropOptions,
-        ) -> Result<Vec<DroppedItem>, Error> {
+        ) -> Result<Vec<DroppedItem<I>>, Error> {
             Ok(vec![])
         }
     }
@@ -353,7 +486,8 @@ mod tests {
             let b1: TestBackend<JsonItem<i32>> = TestBackend::new();
             let b2: TestBackend<JsonItem<i32>> = TestBackend::new();
             let mut c = Combine::new(b1.clone(), b2.clone(), DequeueStrategy::RoundRobin);
-            c.enqueue(&Either::Right(JsonItem::new(42))).await.unwrap();
+            let id = c.enqueue(&Either::Right(JsonItem::new(42))).await.unwrap();
+            assert_eq!(id, Either::Right(0));
 
             let enqueued_b1: Vec<JsonItem<i32>> = b1.get_enqueued().into_iter().collect();
             let expected_b1 = vec![];
@@ -364,6 +498,29 @@ mod tests {
             assert_eq!(enqueued_b2, expected_b2);
         }
 
+        #[tokio::test]
+        async fn batch_enqueues_into_correct_backend() {
+            let b1: TestBackend<JsonItem<i32>> = TestBackend::new();
+            let b2: TestBackend<JsonItem<i32>> = TestBackend::new();
+            let mut c = Combine::new(b1.clone(), b2.clone(), DequeueStrategy::RoundRobin);
+
+            let items = vec![
+                Either::Left(JsonItem::new(1)),
+                Either::Right(JsonItem::new(2)),
+                Either::Left(JsonItem::new(3)),
+            ];
+            let ids = c.enqueue_batch(&items.iter().collect::<Vec<_>>()).await.unwrap();
+
+            // Ids are in item order, tagged with their backend
+            assert_eq!(ids, vec![Either::Left(0), Either::Right(0), Either::Left(1)]);
+
+            let enqueued_b1: Vec<JsonItem<i32>> = b1.get_enqueued().into_iter().collect();
+            assert_eq!(enqueued_b1, vec![JsonItem::new(1), JsonItem::new(3)]);
+
+            let enqueued_b2: Vec<JsonItem<i32>> = b2.get_enqueued().into_iter().collect();
+            assert_eq!(enqueued_b2, vec![JsonItem::new(2)]);
+        }
+
         #[tokio::test]
         async fn dequeues_round_robin() {
             let b1: TestBackend<JsonItem<i32>> = TestBackend::new();
@@ -447,6 +604,29 @@ mod tests {
             assert_eq!(acked_b1, expected_b1);
             assert_eq!(acked_b2, expected_b2);
         }
+
+        #[tokio::test]
+        async fn nacks_into_correct_backend() {
+            let b1: TestBackend<JsonItem<i32>> = TestBackend::new();
+            let b2: TestBackend<JsonItem<i32>> = TestBackend::new();
+            let mut c = Combine::new(b1.clone(), b2.clone(), DequeueStrategy::RoundRobin);
+
+            let nack = vec![
+                Either::left(JsonItem::new(1)),
+                Either::right(JsonItem::new(2)),
+                Either::left(JsonItem::new(3)),
+            ];
+
+            c.nack(&nack.iter().collect::<Vec<_>>(), None).await.unwrap();
+
+            let enqueued_b1: Vec<JsonItem<i32>> = b1.get_enqueued().into_iter().collect();
+            let expected_b1 = vec![JsonItem::new(1), JsonItem::new(3)];
+            assert_eq!(enqueued_b1, expected_b1);
+
+            let enqueued_b2: Vec<JsonItem<i32>> = b2.get_enqueued().into_iter().collect();
+            let expected_b2 = vec![JsonItem::new(2)];
+            assert_eq!(enqueued_b2, expected_b2);
+        }
     }
 
     mod precedence {
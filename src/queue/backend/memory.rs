@@ -0,0 +1,871 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::queue::backend::stream::ATTEMPTS_FIELD;
+use crate::queue::backend::{Backend, DropOptions, DroppedItem, DroppedPayload, due_in};
+use crate::queue::delivery::{Delivery, DeliverySource};
+use crate::queue::error::Error;
+use crate::queue::item::Item;
+use crate::queue::shared::SharedBackend;
+
+#[derive(Clone)]
+pub struct Memory<I: Item> {
+    i: std::marker::PhantomData<I>,
+    state: Arc<Mutex<State>>,
+    notify: Arc<tokio::sync::Notify>,
+    capacity: Option<usize>,
+    redelivery_timeout: Option<Duration>,
+}
+
+pub struct MemoryBuilder {
+    capacity: Option<usize>,
+    redelivery_timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+struct State {
+    last_id: EntryId,
+    ready: VecDeque<Entry>,
+    pending: BTreeMap<EntryId, Pending>,
+    delayed: BTreeMap<(SystemTime, u64), HashMap<String, redis::Value>>,
+    delayed_seq: u64,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct EntryId {
+    ms: u64,
+    seq: u64,
+}
+
+struct Entry {
+    id: EntryId,
+    map: HashMap<String, redis::Value>,
+}
+
+struct Pending {
+    entry: Entry,
+    delivered_at: Instant,
+    deliveries: u64,
+}
+
+impl MemoryBuilder {
+    /// Initialize a memory builder for an unbounded queue that never redelivers.
+    pub fn new() -> Self {
+        Self {
+            capacity: None,
+            redelivery_timeout: None,
+        }
+    }
+
+    /// Bound the number of unacked items (delayed, ready and pending) the queue holds.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Redeliver pending items once they have been idle for at least `timeout`,
+    /// analogous to `AutoclaimOptions::min_idle_time` on a stream.
+    pub fn redelivery_timeout(mut self, timeout: Duration) -> Self {
+        self.redelivery_timeout = Some(timeout);
+        self
+    }
+
+    pub fn build<I: Item>(self) -> Memory<I> {
+        Memory {
+            i: std::marker::PhantomData,
+            state: Arc::new(Mutex::new(State::default())),
+            notify: Arc::new(tokio::sync::Notify::new()),
+            capacity: self.capacity,
+            redelivery_timeout: self.redelivery_timeout,
+        }
+    }
+}
+
+impl Default for MemoryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Item> Memory<I> {
+    /// Push an item, returning its id unless it was delayed.
+    fn push(&self, item: &I, at: Option<SystemTime>) -> Result<Option<String>, Error> {
+        self.push_all(&[item], at).map(|ids| ids.into_iter().next())
+    }
+
+    /// Push all items, or none of them if they would exceed the capacity,
+    /// returning the ids of those pushed as ready.
+    fn push_all(&self, items: &[&I], at: Option<SystemTime>) -> Result<Vec<String>, Error> {
+        let maps = items
+            .iter()
+            .map(|item| {
+                let map = item
+                    .to_stream()?
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), redis::Value::BulkString(v)))
+                    .collect();
+
+                Ok(map)
+            })
+            .collect::<Result<Vec<HashMap<String, redis::Value>>, Error>>()?;
+
+        let mut ids = vec![];
+
+        {
+            let mut state = self.state.lock().unwrap();
+
+            if let Some(capacity) = self.capacity
+                && state.len() + maps.len() > capacity
+            {
+                return Err(Error::CapacityError(capacity));
+            }
+
+            for map in maps {
+                match at {
+                    Some(at) => state.push_delayed(map, at),
+                    None => ids.push(state.push_ready(map).to_string()),
+                }
+            }
+        }
+
+        self.notify.notify_waiters();
+
+        Ok(ids)
+    }
+
+    /// Take up to `n` items, redelivering idle pending ones first. As with
+    /// `XREADGROUP`, an entry that fails to parse is still delivered: it is
+    /// moved to `pending` (or its pending deliveries counted) and fails the
+    /// dequeue, so that redelivery or `drop_items` can clear it. If items were
+    /// taken before it, the batch ends there and the next dequeue fails.
+    fn take(&self, n: usize) -> Result<Vec<Delivery<I>>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut items = vec![];
+        let mut ended = false;
+
+        state.promote_delayed(SystemTime::now());
+
+        if let Some(redelivery_timeout) = self.redelivery_timeout {
+            for pending in state.pending.values_mut() {
+                if items.len() >= n {
+                    break;
+                }
+
+                let idle = now.duration_since(pending.delivered_at);
+                if idle < redelivery_timeout {
+                    continue;
+                }
+
+                let item = match pending.entry.to_item() {
+                    Ok(item) => item,
+                    Err(e) if items.is_empty() => {
+                        pending.delivered_at = now;
+                        pending.deliveries += 1;
+                        return Err(e);
+                    }
+                    Err(_) => {
+                        ended = true;
+                        break;
+                    }
+                };
+
+                pending.delivered_at = now;
+                pending.deliveries += 1;
+                items.push(Delivery {
+                    item,
+                    deliveries: pending.deliveries,
+                    idle,
+                    enqueued_at: Some(pending.entry.id.time()),
+                    source: DeliverySource::Autoclaim,
+                });
+            }
+        }
+
+        while !ended && items.len() < n {
+            let Some(entry) = state.ready.front() else {
+                break;
+            };
+
+            let item = entry.to_item();
+            if item.is_err() && !items.is_empty() {
+                break;
+            }
+
+            let entry = state.ready.pop_front().expect("front entry exists");
+            let id = entry.id;
+            let deliveries = entry.attempts() + 1;
+            state.pending.insert(
+                id,
+                Pending {
+                    entry,
+                    delivered_at: now,
+                    deliveries,
+                },
+            );
+
+            items.push(Delivery {
+                item: item?,
+                deliveries,
+                idle: Duration::ZERO,
+                enqueued_at: Some(id.time()),
+                source: DeliverySource::Read,
+            });
+        }
+
+        Ok(items)
+    }
+}
+
+#[async_trait::async_trait]
+impl<I: Item + Send + Sync> SharedBackend<I> for Memory<I> {
+    type Id = String;
+
+    async fn enqueue(&self, item: &I) -> Result<String, Error> {
+        self.push(item, None).map(|id| id.expect("ready items have an id"))
+    }
+
+    async fn enqueue_at(&self, item: &I, at: SystemTime) -> Result<(), Error> {
+        self.push(item, Some(at)).map(|_| ())
+    }
+
+    /// Enqueues all items, or none of them if they would exceed the capacity.
+    async fn enqueue_batch(&self, items: &[&I]) -> Result<Vec<String>, Error> {
+        self.push_all(items, None)
+    }
+
+    async fn dequeue(
+        &self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<I>, Error> {
+        let deliveries = self.dequeue_deliveries(n, timeout).await?;
+        Ok(deliveries.into_iter().map(|d| d.item).collect())
+    }
+
+    /// Behaves like a blocking `XREADGROUP`: with `Some(timeout)` the call
+    /// waits for new items, where a zero timeout waits indefinitely.
+    async fn dequeue_deliveries(
+        &self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let deadline = timeout
+            .filter(|t| !t.is_zero())
+            .map(|t| tokio::time::Instant::now() + t);
+
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let items = self.take(n)?;
+            if !items.is_empty() || timeout.is_none() {
+                return Ok(items);
+            }
+
+            // Wake up for delayed items becoming due and pending items
+            // becoming due for redelivery, not only for new ones.
+            let wake_at = {
+                let state = self.state.lock().unwrap();
+                let next_due = state.next_due().map(|due| {
+                    let wait = due.duration_since(SystemTime::now()).unwrap_or_default();
+                    tokio::time::Instant::now() + wait
+                });
+                let next_redelivery = self.redelivery_timeout.and_then(|timeout| {
+                    state
+                        .pending
+                        .values()
+                        .filter_map(|p| p.delivered_at.checked_add(timeout))
+                        .min()
+                        .map(tokio::time::Instant::from_std)
+                });
+
+                next_due.into_iter().chain(next_redelivery).min()
+            };
+
+            match (deadline, wake_at) {
+                (Some(deadline), Some(wake_at)) if wake_at < deadline => {
+                    let _ = tokio::time::timeout_at(wake_at, notified).await;
+                }
+                (Some(deadline), _) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return Ok(vec![]);
+                    }
+                }
+                (None, Some(wake_at)) => {
+                    let _ = tokio::time::timeout_at(wake_at, notified).await;
+                }
+                (None, None) => notified.await,
+            }
+        }
+    }
+
+    async fn ack(&self, items: &[&I]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        for id in items.iter().filter_map(|i| i.id()) {
+            if let Some(id) = EntryId::parse(id) {
+                state.pending.remove(&id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Nacked items are requeued as new entries, carrying their delivery count
+    /// over like the stream backend does.
+    async fn nack(&self, items: &[&I], requeue_delay: Option<Duration>) -> Result<(), Error> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let at = requeue_delay.map(due_in).transpose()?;
+
+            for id in items.iter().filter_map(|i| i.id()) {
+                let Some(mut pending) = EntryId::parse(id).and_then(|id| state.pending.remove(&id))
+                else {
+                    continue;
+                };
+
+                pending
+                    .entry
+                    .map
+                    .insert(ATTEMPTS_FIELD.to_string(), redis::Value::Int(pending.deliveries as i64));
+
+                match at {
+                    Some(at) => state.push_delayed(pending.entry.map, at),
+                    None => {
+                        state.push_ready(pending.entry.map);
+                    }
+                }
+            }
+        }
+
+        self.notify.notify_waiters();
+
+        Ok(())
+    }
+
+    /// There is no dead letter, the items are discarded.
+    async fn dead_letter(&self, items: &[&I]) -> Result<(), Error> {
+        SharedBackend::ack(self, items).await
+    }
+
+    async fn drop_items(&self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        let drop = state
+            .pending
+            .iter()
+            .map(|(id, p)| (*id, now.duration_since(p.delivered_at), p.deliveries))
+            .filter(|(_, idle, _)| *idle >= options.min_idle_time)
+            .take(options.scan_limit.map_or(usize::MAX, |limit| limit as usize))
+            .filter(|(_, idle, deliveries)| {
+                *idle > options.min_idle_time && *deliveries >= options.max_deliveries
+            })
+            .collect::<Vec<(EntryId, Duration, u64)>>();
+
+        let dropped = drop
+            .into_iter()
+            .map(|(id, idle, deliveries)| {
+                let pending = state.pending.remove(&id);
+
+                DroppedItem {
+                    id: id.to_string(),
+                    idle: idle.as_millis() as u64,
+                    deliveries,
+                    payload: pending
+                        .filter(|_| options.payloads)
+                        .map(|p| match p.entry.to_item() {
+                            Ok(item) => DroppedPayload::Item(item),
+                            Err(_) => DroppedPayload::Raw(p.entry.map),
+                        }),
+                }
+            })
+            .collect();
+
+        Ok(dropped)
+    }
+}
+
+#[async_trait::async_trait]
+impl<I: Item + Send + Sync> Backend<I> for Memory<I> {
+    type Id = String;
+
+    async fn enqueue(&mut self, item: &I) -> Result<String, Error> {
+        SharedBackend::enqueue(self, item).await
+    }
+
+    async fn enqueue_at(&mut self, item: &I, at: SystemTime) -> Result<(), Error> {
+        SharedBackend::enqueue_at(self, item, at).await
+    }
+
+    async fn enqueue_batch(&mut self, items: &[&I]) -> Result<Vec<String>, Error> {
+        SharedBackend::enqueue_batch(self, items).await
+    }
+
+    async fn dequeue(&mut self, n: usize, timeout: Option<Duration>) -> Result<Vec<I>, Error> {
+        SharedBackend::dequeue(self, n, timeout).await
+    }
+
+    async fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        SharedBackend::dequeue_deliveries(self, n, timeout).await
+    }
+
+    async fn ack(&mut self, items: &Vec<&I>) -> Result<(), Error> {
+        SharedBackend::ack(self, items).await
+    }
+
+    async fn nack(&mut self, items: &[&I], requeue_delay: Option<Duration>) -> Result<(), Error> {
+        SharedBackend::nack(self, items, requeue_delay).await
+    }
+
+    async fn dead_letter(&mut self, items: &[&I]) -> Result<(), Error> {
+        SharedBackend::dead_letter(self, items).await
+    }
+
+    async fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error> {
+        SharedBackend::drop_items(self, options).await
+    }
+}
+
+impl State {
+    fn len(&self) -> usize {
+        self.ready.len() + self.pending.len() + self.delayed.len()
+    }
+
+    fn push_ready(&mut self, map: HashMap<String, redis::Value>) -> EntryId {
+        let id = self.last_id.next();
+        self.last_id = id;
+        self.ready.push_back(Entry { id, map });
+        id
+    }
+
+    fn push_delayed(&mut self, map: HashMap<String, redis::Value>, at: SystemTime) {
+        let seq = self.delayed_seq;
+        self.delayed_seq += 1;
+        self.delayed.insert((at, seq), map);
+    }
+
+    fn promote_delayed(&mut self, now: SystemTime) {
+        while let Some(entry) = self.delayed.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+
+            let map = entry.remove();
+            self.push_ready(map);
+        }
+    }
+
+    fn next_due(&self) -> Option<SystemTime> {
+        self.delayed.keys().next().map(|(at, _)| *at)
+    }
+}
+
+impl EntryId {
+    fn next(&self) -> Self {
+        let ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        if ms > self.ms {
+            Self { ms, seq: 0 }
+        } else {
+            Self {
+                ms: self.ms,
+                seq: self.seq + 1,
+            }
+        }
+    }
+
+    fn time(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(self.ms)
+    }
+
+    fn parse(id: &str) -> Option<Self> {
+        let (ms, seq) = id.split_once('-')?;
+
+        Some(Self {
+            ms: ms.parse().ok()?,
+            seq: seq.parse().ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for EntryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl Entry {
+    /// Deliveries before the entry was last nacked.
+    fn attempts(&self) -> u64 {
+        self.map
+            .get(ATTEMPTS_FIELD)
+            .and_then(|v| redis::from_redis_value(v).ok())
+            .unwrap_or_default()
+    }
+
+    fn to_item<I: Item>(&self) -> Result<I, Error> {
+        let stream_id = redis::streams::StreamId {
+            id: self.id.to_string(),
+            map: self.map.clone(),
+        };
+
+        I::from_stream(&stream_id).map_err(|e| Error::ParseError(stream_id, Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::memory::MemoryBuilder;
+    use crate::queue::{Backend, DeliverySource, DropOptions, DroppedPayload, Error, Item, JsonItem};
+    use std::time::{Duration, SystemTime};
+
+    fn items(dequeued: &[JsonItem<i32>]) -> Vec<i32> {
+        dequeued.iter().map(|i| i.item).collect()
+    }
+
+    /// A `JsonItem` that fails to parse negative values.
+    struct Unsigned(JsonItem<i32>);
+
+    impl Item for Unsigned {
+        fn id(&self) -> Option<&str> {
+            self.0.id()
+        }
+
+        fn from_stream(stream_id: &redis::streams::StreamId) -> Result<Self, Error> {
+            let item = JsonItem::<i32>::from_stream(stream_id)?;
+            if item.item < 0 {
+                return Err(Error::DeserializeError("negative".into()));
+            }
+
+            Ok(Self(item))
+        }
+
+        fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, Error> {
+            self.0.to_stream()
+        }
+    }
+
+    #[tokio::test]
+    async fn enqueue_dequeue() {
+        let mut m = MemoryBuilder::new().build();
+        let id = m.enqueue(&JsonItem::new(123)).await.unwrap();
+
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![123]);
+        assert_eq!(dequeued[0].id, Some(id));
+    }
+
+    #[tokio::test]
+    async fn batch_dequeue() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=5 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1, 2]);
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![3, 4]);
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![5]);
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+    }
+
+    #[tokio::test]
+    async fn blocking_dequeue_wakes_on_enqueue() {
+        let mut m = MemoryBuilder::new().build();
+        let mut producer = m.clone();
+
+        let consumer = tokio::spawn(async move {
+            m.dequeue(1, Some(Duration::from_secs(5))).await.unwrap()
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        producer.enqueue(&JsonItem::new(42)).await.unwrap();
+
+        let dequeued = consumer.await.unwrap();
+        assert_eq!(items(&dequeued), vec![42]);
+    }
+
+    #[tokio::test]
+    async fn blocking_dequeue_times_out() {
+        let mut m = MemoryBuilder::new().build::<JsonItem<i32>>();
+
+        let dequeued = m.dequeue(1, Some(Duration::from_millis(10))).await.unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+    }
+
+    #[tokio::test]
+    async fn redelivers_unacked_items() {
+        let mut m = MemoryBuilder::new()
+            .redelivery_timeout(Duration::from_millis(50))
+            .build();
+        for i in 1..=3 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        // Dequeued but not acked, will be redelivered
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1]);
+
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        m.ack(&dequeued.iter().collect()).await.unwrap();
+        assert_eq!(items(&dequeued), vec![2]);
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn blocking_dequeue_wakes_for_redelivery() {
+        let mut m = MemoryBuilder::new()
+            .redelivery_timeout(Duration::from_millis(50))
+            .build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1]);
+
+        // Blocks indefinitely, but nothing else wakes it
+        let dequeued = tokio::time::timeout(Duration::from_secs(1), m.dequeue(1, Some(Duration::ZERO)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(items(&dequeued), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn delayed_enqueue() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue_at(&JsonItem::new(1), SystemTime::now() + Duration::from_millis(50))
+            .await
+            .unwrap();
+        m.enqueue(&JsonItem::new(2)).await.unwrap();
+
+        // Delayed item is not yet due
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![2]);
+
+        // Blocking dequeue wakes up once the delayed item is due
+        let dequeued = m.dequeue(2, Some(Duration::from_secs(5))).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn nack_requeues_items() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=3 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        m.nack(&dequeued.iter().take(1).collect::<Vec<_>>(), None).await.unwrap();
+        m.nack(&dequeued.iter().skip(1).collect::<Vec<_>>(), Some(Duration::from_millis(50)))
+            .await
+            .unwrap();
+
+        // Immediately nacked item is requeued behind existing items
+        let dequeued = m.dequeue(3, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![3, 1]);
+
+        // Delayed item only comes back once due
+        let dequeued = m.dequeue(3, Some(Duration::from_secs(5))).await.unwrap();
+        assert_eq!(items(&dequeued), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn nack_only_pending_items() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=2 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let dequeued = m.dequeue(2, None).await.unwrap();
+        m.ack(&vec![&dequeued[1]]).await.unwrap();
+
+        // Nacking the same delivery twice requeues it once, and acked items
+        // aren't requeued at all
+        m.nack(&[&dequeued[0]], None).await.unwrap();
+        m.nack(&[&dequeued[0]], None).await.unwrap();
+        m.nack(&[&dequeued[1]], None).await.unwrap();
+
+        let dequeued = m.dequeue(5, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn nack_preserves_deliveries() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        for deliveries in 1..=3 {
+            let dequeued = m.dequeue_deliveries(1, None).await.unwrap();
+            assert_eq!(dequeued[0].deliveries, deliveries);
+            m.nack(&[&dequeued[0].item], None).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn delivery_metadata() {
+        let mut m = MemoryBuilder::new()
+            .redelivery_timeout(Duration::from_millis(50))
+            .build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let dequeued = m.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued[0].item.item, 1);
+        assert_eq!(dequeued[0].deliveries, 1);
+        assert_eq!(dequeued[0].idle, Duration::ZERO);
+        assert_eq!(dequeued[0].source, DeliverySource::Read);
+        assert_eq!(dequeued[0].enqueued_at.unwrap() <= SystemTime::now(), true);
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        let dequeued = m.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued[0].item.item, 1);
+        assert_eq!(dequeued[0].deliveries, 2);
+        assert_eq!(dequeued[0].idle >= Duration::from_millis(50), true);
+        assert_eq!(dequeued[0].source, DeliverySource::Autoclaim);
+    }
+
+    #[tokio::test]
+    async fn parse_failure_moves_entry_to_pending() {
+        let mut m = MemoryBuilder::new().build();
+        for i in [1, -1, 2] {
+            m.enqueue(&Unsigned(JsonItem::new(i))).await.unwrap();
+        }
+
+        // Items taken before the failure are returned, ending the batch
+        let dequeued = m.dequeue(3, None).await.unwrap();
+        assert_eq!(dequeued.iter().map(|i| i.0.item).collect::<Vec<_>>(), vec![1]);
+        m.ack(&dequeued.iter().collect()).await.unwrap();
+
+        // The entry that failed is moved to pending, failing the dequeue
+        let res = m.dequeue(3, None).await;
+        assert_eq!(
+            matches!(res, Err(Error::ParseError(_, e)) if matches!(*e, Error::DeserializeError(_))),
+            true
+        );
+
+        // Items behind it are still delivered
+        let dequeued = m.dequeue(3, None).await.unwrap();
+        assert_eq!(dequeued.iter().map(|i| i.0.item).collect::<Vec<_>>(), vec![2]);
+        m.ack(&dequeued.iter().collect()).await.unwrap();
+
+        std::thread::sleep(Duration::from_millis(10));
+
+        // The failed entry can be dropped
+        let drop_options = DropOptions {
+            min_idle_time: Duration::ZERO,
+            max_deliveries: 1,
+            count: 10,
+            payloads: true,
+            ..Default::default()
+        };
+        let dropped = m.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].deliveries, 1);
+        assert_eq!(matches!(dropped[0].payload, Some(DroppedPayload::Raw(_))), true);
+    }
+
+    #[tokio::test]
+    async fn rejects_items_over_capacity() {
+        let mut m = MemoryBuilder::new().capacity(2).build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+        m.enqueue(&JsonItem::new(2)).await.unwrap();
+        assert_eq!(m.enqueue(&JsonItem::new(3)).await.is_err(), true);
+
+        // Pending items still count towards capacity until acked
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        assert_eq!(m.enqueue(&JsonItem::new(3)).await.is_err(), true);
+
+        m.ack(&dequeued.iter().collect()).await.unwrap();
+        m.enqueue(&JsonItem::new(3)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_enqueue() {
+        let mut m = MemoryBuilder::new().capacity(3).build();
+        let batch = [JsonItem::new(1), JsonItem::new(2)];
+
+        let ids = m.enqueue_batch(&batch.iter().collect::<Vec<_>>()).await.unwrap();
+        assert_eq!(ids.len(), 2);
+
+        // A batch over capacity is rejected as a whole
+        assert_eq!(m.enqueue_batch(&batch.iter().collect::<Vec<_>>()).await.is_err(), true);
+
+        let dequeued = m.dequeue(3, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1, 2]);
+        assert_eq!(dequeued.iter().map(|i| i.id.clone().unwrap()).collect::<Vec<_>>(), ids);
+    }
+
+    #[tokio::test]
+    async fn drop_items() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=5 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        // Dequeued but not acked, will be dropped
+        let dequeued = m.dequeue(3, None).await.unwrap();
+        assert_eq!(items(&dequeued), vec![1, 2, 3]);
+
+        // Dequeued and acked, not pending at drop time
+        let dequeued2 = m.dequeue(2, None).await.unwrap();
+        m.ack(&dequeued2.iter().collect()).await.unwrap();
+
+        let drop_options = DropOptions {
+            min_idle_time: Duration::from_millis(50),
+            max_deliveries: 1,
+            count: 2,
+            scan_limit: Some(2),
+            ..Default::default()
+        };
+
+        // Nothing will be dropped because no pending items exceed min idle time
+        let dropped = m.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.is_empty(), true);
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        // First two dequeued items will be dropped
+        let dropped = m.drop_items(&drop_options).await.unwrap();
+        let dropped_ids: Vec<String> = dropped.into_iter().map(|i| i.id).collect();
+        let dequeued_ids: Vec<String> = dequeued
+            .iter()
+            .take(2)
+            .map(|i| i.id.clone().unwrap())
+            .collect();
+        assert_eq!(dropped_ids, dequeued_ids);
+
+        // Third item remains pending and is dropped next, with its payload
+        let drop_options = DropOptions {
+            payloads: true,
+            ..drop_options
+        };
+        let dropped = m.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].id, dequeued[2].id.clone().unwrap());
+        assert_eq!(
+            matches!(&dropped[0].payload, Some(DroppedPayload::Item(i)) if i.item == 3),
+            true
+        );
+    }
+}
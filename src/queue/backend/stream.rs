@@ -1,18 +1,302 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::future::BoxFuture;
 use redis::AsyncCommands;
 
-use crate::queue::backend::{Backend, DropOptions, DroppedItem};
+use crate::queue::backend::{Backend, DropOptions, DroppedItem, DroppedPayload, due_in};
+use crate::queue::delivery::{Delivery, DeliverySource, stream_id_time};
 use crate::queue::error::Error;
 use crate::queue::item::Item;
+use crate::queue::shared::SharedBackend;
 
-#[derive(Clone)]
+/// Adds an item to the delayed sorted set (`KEYS[1]`) as the member `ARGV[1]`
+/// due at `ARGV[2]`, storing its fields (the rest of `ARGV`) msgpack-encoded
+/// under the same member in the payloads hash (`KEYS[2]`).
+const ENQUEUE_DELAYED: &str = r#"
+local fields = {}
+for i = 3, #ARGV do
+    table.insert(fields, ARGV[i])
+end
+redis.call('HSET', KEYS[2], ARGV[1], cmsgpack.pack(fields))
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[1])
+"#;
+
+/// Moves due items from the delayed sorted set (`KEYS[2]`) into the stream
+/// (`KEYS[1]`), taking their fields from the payloads hash (`KEYS[3]`).
+/// `ARGV` holds the current time, the limit and the `trim_args`. Returns the
+/// score of the next item still pending, if any.
+const PROMOTE_DELAYED: &str = r#"
+local function add(fields)
+    if ARGV[3] == '' then
+        return redis.call('XADD', KEYS[1], '*', unpack(fields))
+    end
+    return redis.call('XADD', KEYS[1], ARGV[3], ARGV[4], ARGV[5], '*', unpack(fields))
+end
+local due = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+for _, member in ipairs(due) do
+    local payload = redis.call('HGET', KEYS[3], member)
+    if payload then
+        add(cmsgpack.unpack(payload))
+    end
+    redis.call('HDEL', KEYS[3], member)
+    redis.call('ZREM', KEYS[2], member)
+end
+local next = redis.call('ZRANGE', KEYS[2], 0, 0, 'WITHSCORES')
+return next[2]
+"#;
+
+/// Upper bound on the number of delayed items promoted per dequeue.
+pub(crate) const PROMOTE_DELAYED_LIMIT: usize = 100;
+
+/// Default for `StreamBuilder::delayed_poll_interval`.
+pub(crate) const DELAYED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) static ENQUEUE_DELAYED_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(ENQUEUE_DELAYED));
+
+pub(crate) static PROMOTE_DELAYED_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(PROMOTE_DELAYED));
+
+/// Copies pending entries of the stream (`KEYS[1]`) into the dead-letter
+/// stream (`KEYS[2]`) along with their delivery metadata, then acks them.
+/// `ARGV[1]` is the group, followed by `(id, deliveries, idle)` triples.
+const DEAD_LETTER: &str = r#"
+for i = 2, #ARGV, 3 do
+    local id = ARGV[i]
+    local entries = redis.call('XRANGE', KEYS[1], id, id)
+    if #entries > 0 then
+        local fields = entries[1][2]
+        table.insert(fields, 'rdq-source-stream')
+        table.insert(fields, KEYS[1])
+        table.insert(fields, 'rdq-source-id')
+        table.insert(fields, id)
+        table.insert(fields, 'rdq-group')
+        table.insert(fields, ARGV[1])
+        table.insert(fields, 'rdq-deliveries')
+        table.insert(fields, ARGV[i + 1])
+        table.insert(fields, 'rdq-idle')
+        table.insert(fields, ARGV[i + 2])
+        redis.call('XADD', KEYS[2], '*', unpack(fields))
+    end
+    redis.call('XACK', KEYS[1], ARGV[1], id)
+end
+"#;
+
+/// Moves entries from the dead-letter stream (`KEYS[1]`) back into the
+/// stream (`KEYS[2]`), stripping the dead-letter metadata and attempts. `ARGV` holds the
+/// dead-letter entry ids. Returns the number of entries requeued.
+const REQUEUE_DEAD_LETTERS: &str = r#"
+local metadata = {
+    ['rdq-source-stream'] = true,
+    ['rdq-source-id'] = true,
+    ['rdq-group'] = true,
+    ['rdq-deliveries'] = true,
+    ['rdq-idle'] = true,
+    ['rdq-attempts'] = true,
+}
+local requeued = 0
+for _, id in ipairs(ARGV) do
+    local entries = redis.call('XRANGE', KEYS[1], id, id)
+    if #entries > 0 then
+        local fields = {}
+        local entry = entries[1][2]
+        for j = 1, #entry, 2 do
+            if not metadata[entry[j]] then
+                table.insert(fields, entry[j])
+                table.insert(fields, entry[j + 1])
+            end
+        end
+        redis.call('XADD', KEYS[2], '*', unpack(fields))
+        redis.call('XDEL', KEYS[1], id)
+        requeued = requeued + 1
+    end
+end
+return requeued
+"#;
+
+/// Re-adds pending entries of the stream (`KEYS[1]`) as new entries, either
+/// immediately or via the delayed sorted set (`KEYS[2]`) and payloads hash
+/// (`KEYS[3]`) when `ARGV[3]` holds a due time, then acks the originals. The deliveries so far are carried in
+/// the `rdq-attempts` field. `ARGV` holds the group and consumer, the due
+/// time and the `trim_args`, followed by the entry ids. Entries that aren't
+/// pending for the consumer, e.g. already acked or nacked, are skipped.
+const NACK: &str = r#"
+local function add(fields)
+    if ARGV[4] == '' then
+        return redis.call('XADD', KEYS[1], '*', unpack(fields))
+    end
+    return redis.call('XADD', KEYS[1], ARGV[4], ARGV[5], ARGV[6], '*', unpack(fields))
+end
+local due = tonumber(ARGV[3])
+for i = 7, #ARGV do
+    local id = ARGV[i]
+    local pending = redis.call('XPENDING', KEYS[1], ARGV[1], id, id, 1, ARGV[2])
+    if #pending > 0 then
+        local entries = redis.call('XRANGE', KEYS[1], id, id)
+        if #entries > 0 then
+            local attempts = pending[1][4]
+            local fields = {}
+            local entry = entries[1][2]
+            for j = 1, #entry, 2 do
+                if entry[j] == 'rdq-attempts' then
+                    attempts = attempts + tonumber(entry[j + 1])
+                else
+                    table.insert(fields, entry[j])
+                    table.insert(fields, entry[j + 1])
+                end
+            end
+            table.insert(fields, 'rdq-attempts')
+            table.insert(fields, tostring(attempts))
+            if due > 0 then
+                redis.call('HSET', KEYS[3], id, cmsgpack.pack(fields))
+                redis.call('ZADD', KEYS[2], due, id)
+            else
+                add(fields)
+            end
+        end
+        redis.call('XACK', KEYS[1], ARGV[1], id)
+    end
+end
+"#;
+
+/// Runs `XPENDING` on the stream (`KEYS[1]`) with the arguments in `ARGV`,
+/// adding the deliveries carried over by `NACK` in `rdq-attempts` to each
+/// entry's delivery count, which restarts as nacked items are re-added.
+const PENDING: &str = r#"
+local pending = redis.call('XPENDING', KEYS[1], unpack(ARGV))
+for _, info in ipairs(pending) do
+    local entries = redis.call('XRANGE', KEYS[1], info[1], info[1])
+    if #entries > 0 then
+        local entry = entries[1][2]
+        for j = 1, #entry, 2 do
+            if entry[j] == 'rdq-attempts' then
+                info[4] = info[4] + tonumber(entry[j + 1])
+            end
+        end
+    end
+end
+return pending
+"#;
+
+/// Autoclaims idle entries of the stream (`KEYS[1]`), returning the pending
+/// info (idle time and delivery count) of the claimed entries as it was before
+/// the claim alongside the `XAUTOCLAIM` reply. `ARGV` holds the group,
+/// consumer, min idle time, start id and count.
+const AUTOCLAIM: &str = r#"
+local pending = redis.call('XPENDING', KEYS[1], ARGV[1], 'IDLE', ARGV[3], ARGV[4], '+', ARGV[5])
+local claimed = redis.call('XAUTOCLAIM', KEYS[1], ARGV[1], ARGV[2], ARGV[3], ARGV[4], 'COUNT', ARGV[5])
+return {pending, claimed}
+"#;
+
+/// Acks entries of the stream (`KEYS[1]`) for the group `ARGV[1]`, then
+/// deletes those that every group reading the stream has both delivered and
+/// acked. `ARGV` continues with the entry ids. Returns the number deleted.
+const ACK_DELETE: &str = r#"
+local function parse_id(id)
+    local ms, seq = string.match(id, '(%d+)-(%d+)')
+    return tonumber(ms), tonumber(seq)
+end
+local function delivered(id, last)
+    local ms, seq = parse_id(id)
+    local last_ms, last_seq = parse_id(last)
+    return ms < last_ms or (ms == last_ms and seq <= last_seq)
+end
+local groups = {}
+for _, info in ipairs(redis.call('XINFO', 'GROUPS', KEYS[1])) do
+    local group = {}
+    for j = 1, #info, 2 do
+        group[info[j]] = info[j + 1]
+    end
+    table.insert(groups, group)
+end
+local deleted = 0
+for i = 2, #ARGV do
+    local id = ARGV[i]
+    redis.call('XACK', KEYS[1], ARGV[1], id)
+    local acked = true
+    for _, group in ipairs(groups) do
+        if not delivered(id, group['last-delivered-id'])
+            or #redis.call('XPENDING', KEYS[1], group['name'], id, id, 1) > 0 then
+            acked = false
+            break
+        end
+    end
+    if acked then
+        deleted = deleted + redis.call('XDEL', KEYS[1], id)
+    end
+end
+return deleted
+"#;
+
+/// Rereads pending entries of this consumer after the id `ARGV[3]`, returning
+/// their pending info (idle time and delivery count) as it was before the read
+/// alongside the `XREADGROUP` reply. `ARGV` holds the group, consumer, start
+/// id and count.
+const RECOVER: &str = r#"
+local pending = redis.call('XPENDING', KEYS[1], ARGV[1], '(' .. ARGV[3], '+', ARGV[4], ARGV[2])
+local read = redis.call('XREADGROUP', 'GROUP', ARGV[1], ARGV[2], 'COUNT', ARGV[4], 'STREAMS', KEYS[1], ARGV[3])
+return {pending, read}
+"#;
+
+pub(crate) static AUTOCLAIM_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(AUTOCLAIM));
+
+pub(crate) static NACK_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(NACK));
+
+pub(crate) static DEAD_LETTER_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(DEAD_LETTER));
+
+static PENDING_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(PENDING));
+
+static REQUEUE_DEAD_LETTERS_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(REQUEUE_DEAD_LETTERS));
+
+static RECOVER_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(RECOVER));
+
+static ACK_DELETE_SCRIPT: std::sync::LazyLock<redis::Script> =
+    std::sync::LazyLock::new(|| redis::Script::new(ACK_DELETE));
+
+/// Called with the ids of pending entries found deleted from the stream.
+type DeletedCallback = Arc<dyn Fn(&[String]) + Send + Sync>;
+
+/// Enqueues a dropped entry, with the dead-letter metadata fields added, into
+/// a dead-letter backend as a `DeadLetterItem`.
+type DeadLetterEnqueue =
+    Arc<dyn Fn(redis::streams::StreamId) -> BoxFuture<'static, Result<(), Error>> + Send + Sync>;
+
+/// Clones share their dequeue state, so a stream can be cloned across tasks
+/// (or used through `SharedQueue`) without forking the autoclaim schedule.
+///
+/// Commands share one multiplexed connection, except for blocking reads,
+/// which each take a connection of their own so that a dequeue waiting on
+/// an empty stream doesn't hold up other tasks' enqueues and acks.
 pub struct Stream<I: Item> {
     i: std::marker::PhantomData<I>,
     redis: redis::aio::ConnectionManager,
+    client: redis::Client,
+    blocking_connections: Arc<Mutex<Vec<redis::aio::MultiplexedConnection>>>,
     stream_key: String,
+    delayed_key: String,
+    payloads_key: String,
     queue_name: String,
     consumer: String,
     autoclaim_options: Option<AutoclaimOptions>,
-    dequeue_stage: DequeueStage,
+    dequeue_stage: Arc<Mutex<DequeueStage>>,
+    delayed_schedule: Arc<Mutex<DelayedSchedule>>,
+    delayed_poll_interval: Duration,
+    dead_letter: Option<DeadLetter>,
+    parse_failure_policy: ParseFailurePolicy,
+    parse_failures: Arc<Mutex<Vec<redis::streams::StreamId>>>,
+    atomic_batches: bool,
+    retention: Option<Retention>,
+    delete_on_ack: bool,
+    fill_batches: bool,
+    on_deleted: Option<DeletedCallback>,
 }
 
 pub struct StreamBuilder {
@@ -21,18 +305,386 @@ pub struct StreamBuilder {
     queue_name: String,
     consumer: String,
     autoclaim_options: Option<AutoclaimOptions>,
+    dead_letter: Option<DeadLetter>,
+    parse_failure_policy: ParseFailurePolicy,
+    atomic_batches: bool,
+    retention: Option<Retention>,
+    delete_on_ack: bool,
+    start_from: StartFrom,
+    recover_pending: bool,
+    fill_batches: bool,
+    on_deleted: Option<DeletedCallback>,
+    delayed_poll_interval: Duration,
 }
 
 #[derive(Clone)]
 pub struct AutoclaimOptions {
     pub frequency: usize,
     pub min_idle_time: std::time::Duration,
+    /// Also wait for this long since the previous autoclaim, so that it runs
+    /// at most once per interval however many dequeues there are. Blocking
+    /// reads return early once it's due. With a `frequency` of 1 the
+    /// schedule is purely time-based.
+    pub interval: Option<Duration>,
+}
+
+/// Autoclaim every 10th dequeue, entries idle for at least a minute.
+impl Default for AutoclaimOptions {
+    fn default() -> Self {
+        Self {
+            frequency: 10,
+            min_idle_time: Duration::from_secs(60),
+            interval: None,
+        }
+    }
+}
+
+/// Where a newly created consumer group starts reading. Has no effect if the
+/// group already exists, see `Stream::set_group_position` to move it.
+#[derive(Clone, Debug, Default)]
+pub enum StartFrom {
+    /// Deliver every entry already in the stream.
+    Beginning,
+    /// Only deliver entries added after the group is created.
+    #[default]
+    Latest,
+    /// Deliver entries after this stream id.
+    Id(String),
+    /// Deliver entries added at or after this time.
+    Timestamp(SystemTime),
+}
+
+impl StartFrom {
+    /// The group's last delivered id for this start position.
+    fn last_delivered_id(&self) -> String {
+        match self {
+            Self::Beginning => "0".to_string(),
+            Self::Latest => "$".to_string(),
+            Self::Id(id) => id.clone(),
+            Self::Timestamp(at) => match unix_millis(*at) {
+                0 => "0".to_string(),
+                ms => format!("{}-{}", ms - 1, u64::MAX),
+            },
+        }
+    }
+}
+
+/// Bounds the size of the stream. Trimming removes the oldest entries whether
+/// or not they have been delivered or acked.
+#[derive(Clone, Debug)]
+pub enum Retention {
+    /// Keep at most `len` entries (`MAXLEN`).
+    MaxLen { len: usize, approximate: bool },
+    /// Keep entries added within the last `age` (`MINID`).
+    MaxAge { age: Duration, approximate: bool },
+}
+
+/// Where `drop_items` moves dropped entries before acking them.
+#[derive(Clone)]
+pub enum DeadLetter {
+    /// Another stream key, receiving the original entry fields plus
+    /// `rdq-source-stream`, `rdq-source-id`, `rdq-group`, `rdq-deliveries`
+    /// and `rdq-idle`.
+    Stream(String),
+    /// Any backend of `DeadLetterItem`s, receiving the same fields as
+    /// `DeadLetter::Stream` whether or not they parse as an item.
+    Backend(DeadLetterEnqueue),
+}
+
+/// How dequeue handles entries that `Item::from_stream` can't parse.
+#[derive(Clone, Default)]
+pub enum ParseFailurePolicy {
+    /// Fail the whole dequeue with `Error::ParseError`, carrying the error the
+    /// entry failed with, and leave the entry pending.
+    #[default]
+    Error,
+    /// Ack and discard the entry.
+    Skip,
+    /// Move the entry into another stream, with the same metadata fields as
+    /// `DeadLetter::Stream`, and ack it.
+    Quarantine(String),
+    /// Leave the entry pending and collect it for `Stream::take_parse_failures`,
+    /// returning the parsed items. The entry is collected again whenever it is
+    /// autoclaimed.
+    Collect,
+}
+
+/// Entry fields added to dead-lettered entries, see `DeadLetter::Stream`.
+const DEAD_LETTER_FIELDS: [&str; 5] = [
+    "rdq-source-stream",
+    "rdq-source-id",
+    "rdq-group",
+    "rdq-deliveries",
+    "rdq-idle",
+];
+
+/// A dead-lettered entry, as listed by `Stream::dead_letters` or received by
+/// a `DeadLetter::Backend`. Stored with the dead-letter stream layout.
+#[derive(Clone, Debug)]
+pub struct DeadLetterItem<I> {
+    pub id: String,
+    /// The parsed item, if the entry could be parsed.
+    pub item: Option<I>,
+    /// The original entry fields, whether or not they parse.
+    pub fields: HashMap<String, redis::Value>,
+    pub source_stream: String,
+    pub source_id: String,
+    pub group: String,
+    pub deliveries: u64,
+    pub idle: u64,
+}
+
+impl<I: Item> Item for DeadLetterItem<I> {
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+
+    fn from_stream(stream_id: &redis::streams::StreamId) -> Result<Self, Error> {
+        let fields = stream_id
+            .map
+            .iter()
+            .filter(|(k, _)| !DEAD_LETTER_FIELDS.contains(&k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        let instance = Self {
+            id: stream_id.id.clone(),
+            item: I::from_stream(stream_id).ok(),
+            fields,
+            source_stream: stream_id.get("rdq-source-stream").unwrap_or_default(),
+            source_id: stream_id.get("rdq-source-id").unwrap_or_default(),
+            group: stream_id.get("rdq-group").unwrap_or_default(),
+            deliveries: stream_id.get("rdq-deliveries").unwrap_or_default(),
+            idle: stream_id.get("rdq-idle").unwrap_or_default(),
+        };
+
+        Ok(instance)
+    }
+
+    fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, Error> {
+        let mut fields = self
+            .fields
+            .iter()
+            .map(|(k, v)| {
+                let value = redis::from_redis_value(v).map_err(|e| Error::SerializeError(Box::new(e)))?;
+                Ok((k.as_str(), value))
+            })
+            .collect::<Result<Vec<(&str, Vec<u8>)>, Error>>()?;
+        fields.sort();
+
+        let metadata = [
+            self.source_stream.clone(),
+            self.source_id.clone(),
+            self.group.clone(),
+            self.deliveries.to_string(),
+            self.idle.to_string(),
+        ];
+        fields.extend(DEAD_LETTER_FIELDS.into_iter().zip(metadata.map(String::into_bytes)));
+
+        Ok(fields)
+    }
 }
 
 #[derive(Clone)]
-enum DequeueStage {
+pub(crate) enum DequeueStage {
+    /// Rereading this consumer's own pending entries, before any new ones.
+    Recover { next_stream_id: String },
+    /// Another handle is recovering, and will set the next stage once done.
+    Recovering,
     Autoclaim { next_stream_id: String },
-    Read { next_autoclaim: Option<usize> },
+    /// Another handle is autoclaiming, and will set the next stage once done.
+    Autoclaiming,
+    Read {
+        next_autoclaim: Option<usize>,
+        autoclaim_at: Option<Instant>,
+    },
+}
+
+impl DequeueStage {
+    /// Reading, with a fresh autoclaim schedule.
+    pub(crate) fn read(options: Option<&AutoclaimOptions>) -> Self {
+        Self::Read {
+            next_autoclaim: options.map(|o| o.frequency),
+            autoclaim_at: options.and_then(|o| o.interval).map(|i| Instant::now() + i),
+        }
+    }
+
+    /// The stage following a read from `Read { next_autoclaim, autoclaim_at }`.
+    /// Once counted down, an interval schedule stays put until `autoclaim_due`.
+    pub(crate) fn after_read(next_autoclaim: Option<usize>, autoclaim_at: Option<Instant>) -> Self {
+        match next_autoclaim {
+            Some(next_autoclaim) if next_autoclaim <= 1 && autoclaim_at.is_none() => {
+                Self::Autoclaim {
+                    next_stream_id: "0-0".to_string(),
+                }
+            }
+            next_autoclaim => Self::Read {
+                next_autoclaim: next_autoclaim.map(|n| n.saturating_sub(1).max(1)),
+                autoclaim_at,
+            },
+        }
+    }
+
+    /// Whether an interval schedule has counted down and its time has come.
+    pub(crate) fn autoclaim_due(&self) -> bool {
+        self.autoclaim_deadline()
+            .is_some_and(|at| at <= Instant::now())
+    }
+
+    /// When the next dequeue should autoclaim, for an interval schedule that
+    /// has counted down.
+    pub(crate) fn autoclaim_deadline(&self) -> Option<Instant> {
+        match self {
+            Self::Read {
+                next_autoclaim: Some(next_autoclaim),
+                autoclaim_at,
+            } if *next_autoclaim <= 1 => *autoclaim_at,
+            _ => None,
+        }
+    }
+
+    /// Take the next dequeue step. The stage is advanced before any I/O so
+    /// that concurrent handles sharing it don't repeat the same step.
+    pub(crate) fn claim(&mut self) -> DequeueStep {
+        if self.autoclaim_due() {
+            *self = Self::Autoclaiming;
+            return DequeueStep::Autoclaim("0-0".to_string());
+        }
+
+        match self.clone() {
+            Self::Read {
+                next_autoclaim,
+                autoclaim_at,
+            } => {
+                *self = Self::after_read(next_autoclaim, autoclaim_at);
+                DequeueStep::Read {
+                    autoclaim_at: self.autoclaim_deadline(),
+                }
+            }
+            Self::Recover { next_stream_id } => {
+                *self = Self::Recovering;
+                DequeueStep::Recover(next_stream_id)
+            }
+            Self::Autoclaim { next_stream_id } => {
+                *self = Self::Autoclaiming;
+                DequeueStep::Autoclaim(next_stream_id)
+            }
+            Self::Recovering | Self::Autoclaiming => DequeueStep::Read { autoclaim_at: None },
+        }
+    }
+}
+
+/// Puts a claimed `Recover`/`Autoclaim` stage back unless its step completes,
+/// so that a step which fails, or whose dequeue is dropped midway, is retried
+/// by the next dequeue instead of leaving the stage `Recovering` or
+/// `Autoclaiming` for good.
+pub(crate) struct StageGuard<'a> {
+    stage: &'a Mutex<DequeueStage>,
+    restore: Option<DequeueStage>,
+}
+
+impl<'a> StageGuard<'a> {
+    pub(crate) fn new(stage: &'a Mutex<DequeueStage>, restore: DequeueStage) -> Self {
+        Self {
+            stage,
+            restore: Some(restore),
+        }
+    }
+
+    /// Complete the step, moving the stage on to `next`.
+    pub(crate) fn complete(mut self, next: DequeueStage) {
+        self.restore = None;
+        *self.stage.lock().unwrap() = next;
+    }
+}
+
+impl Drop for StageGuard<'_> {
+    fn drop(&mut self) {
+        if let (Some(restore), Ok(mut stage)) = (self.restore.take(), self.stage.lock()) {
+            *stage = restore;
+        }
+    }
+}
+
+/// When dequeues next need to promote delayed items. Items delayed through
+/// this stream (or its clones) are tracked as they are enqueued, so that
+/// dequeues only run `PROMOTE_DELAYED` once something is due, or every poll
+/// interval to pick up items delayed elsewhere.
+pub(crate) struct DelayedSchedule {
+    /// When the next delayed item known of becomes due.
+    next_due: Option<SystemTime>,
+    /// When to next run `PROMOTE_DELAYED`.
+    check_at: SystemTime,
+}
+
+impl DelayedSchedule {
+    /// A schedule checking on the first dequeue.
+    pub(crate) fn new() -> Self {
+        Self {
+            next_due: None,
+            check_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    /// Whether dequeues need to run `PROMOTE_DELAYED` at `now`.
+    pub(crate) fn check_due(&self, now: SystemTime) -> bool {
+        self.check_at <= now
+    }
+
+    /// When dequeues next need to run `PROMOTE_DELAYED`.
+    pub(crate) fn check_at(&self) -> SystemTime {
+        self.check_at
+    }
+
+    /// Record the next due time returned by `PROMOTE_DELAYED`.
+    pub(crate) fn promoted(&mut self, next_due: Option<SystemTime>, poll_interval: Duration) {
+        let poll_at = SystemTime::now() + poll_interval;
+
+        self.next_due = next_due;
+        self.check_at = next_due.map_or(poll_at, |due| due.min(poll_at));
+    }
+
+    /// Record an item delayed until `at`.
+    pub(crate) fn delayed(&mut self, at: SystemTime) {
+        self.next_due = Some(self.next_due.map_or(at, |due| due.min(at)));
+        self.check_at = self.check_at.min(at);
+    }
+}
+
+/// What a single dequeue does, as claimed from the `DequeueStage`.
+pub(crate) enum DequeueStep {
+    /// Read new entries, returning by the deadline if there is one.
+    Read { autoclaim_at: Option<Instant> },
+    Recover(String),
+    Autoclaim(String),
+}
+
+impl<I: Item> Clone for Stream<I> {
+    fn clone(&self) -> Self {
+        Self {
+            i: std::marker::PhantomData,
+            redis: self.redis.clone(),
+            client: self.client.clone(),
+            blocking_connections: self.blocking_connections.clone(),
+            stream_key: self.stream_key.clone(),
+            delayed_key: self.delayed_key.clone(),
+            payloads_key: self.payloads_key.clone(),
+            queue_name: self.queue_name.clone(),
+            consumer: self.consumer.clone(),
+            autoclaim_options: self.autoclaim_options.clone(),
+            dequeue_stage: self.dequeue_stage.clone(),
+            delayed_schedule: self.delayed_schedule.clone(),
+            delayed_poll_interval: self.delayed_poll_interval,
+            dead_letter: self.dead_letter.clone(),
+            parse_failure_policy: self.parse_failure_policy.clone(),
+            parse_failures: self.parse_failures.clone(),
+            atomic_batches: self.atomic_batches,
+            retention: self.retention.clone(),
+            delete_on_ack: self.delete_on_ack,
+            fill_batches: self.fill_batches,
+            on_deleted: self.on_deleted.clone(),
+        }
+    }
 }
 
 impl StreamBuilder {
@@ -48,6 +700,16 @@ impl StreamBuilder {
             queue_name: queue_name.into(),
             consumer: uuid::Uuid::new_v4().to_string(),
             autoclaim_options: None,
+            dead_letter: None,
+            parse_failure_policy: ParseFailurePolicy::default(),
+            atomic_batches: false,
+            retention: None,
+            delete_on_ack: false,
+            start_from: StartFrom::default(),
+            recover_pending: false,
+            fill_batches: false,
+            on_deleted: None,
+            delayed_poll_interval: DELAYED_POLL_INTERVAL,
         }
     }
 
@@ -61,28 +723,126 @@ impl StreamBuilder {
         self
     }
 
+    /// Move items dropped by `drop_items` into another stream instead of
+    /// discarding them.
+    pub fn dead_letter_stream(mut self, stream_key: impl Into<String>) -> Self {
+        self.dead_letter = Some(DeadLetter::Stream(stream_key.into()));
+        self
+    }
+
+    /// Move items dropped by `drop_items` into `backend` instead of
+    /// discarding them, along with their delivery metadata. Entries that
+    /// can't be parsed as `I` are moved too, with only their raw fields.
+    pub fn dead_letter_backend<I, B>(mut self, backend: B) -> Self
+    where
+        I: Item + Send + Sync + 'static,
+        B: Backend<DeadLetterItem<I>> + Send + 'static,
+    {
+        let backend = Arc::new(tokio::sync::Mutex::new(backend));
+        let enqueue: DeadLetterEnqueue = Arc::new(move |entry| {
+            let backend = backend.clone();
+            Box::pin(async move {
+                let item = DeadLetterItem::<I>::from_stream(&entry)?;
+                backend.lock().await.enqueue(&item).await?;
+                Ok(())
+            })
+        });
+
+        self.dead_letter = Some(DeadLetter::Backend(enqueue));
+        self
+    }
+
+    pub fn parse_failure_policy(mut self, policy: ParseFailurePolicy) -> Self {
+        self.parse_failure_policy = policy;
+        self
+    }
+
+    /// Wrap `enqueue_batch` pipelines in `MULTI`/`EXEC`, so that either all
+    /// items of a batch are added or none are.
+    pub fn atomic_batches(mut self, atomic: bool) -> Self {
+        self.atomic_batches = atomic;
+        self
+    }
+
+    /// Trim the stream on every `XADD`, and in `Stream::trim`.
+    pub fn retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Delete entries once every consumer group reading the stream has
+    /// acked them, whether by `ack`, `nack` or `drop_items`.
+    pub fn delete_on_ack(mut self, delete: bool) -> Self {
+        self.delete_on_ack = delete;
+        self
+    }
+
+    /// Where to start reading if the consumer group doesn't exist yet.
+    pub fn start_from(mut self, start_from: StartFrom) -> Self {
+        self.start_from = start_from;
+        self
+    }
+
+    /// Before reading new entries, redeliver those still pending for this
+    /// consumer, e.g. left in flight when a consumer with a fixed name
+    /// restarted. Without this they are only recovered through autoclaim.
+    pub fn recover_pending(mut self, recover: bool) -> Self {
+        self.recover_pending = recover;
+        self
+    }
+
+    /// Top up dequeues that autoclaim (or recover) fewer than `n` items with
+    /// new entries, rather than returning a short batch every `frequency`
+    /// dequeues. The dequeue only blocks if nothing was reclaimed.
+    pub fn fill_batches(mut self, fill: bool) -> Self {
+        self.fill_batches = fill;
+        self
+    }
+
+    /// Call `f` with the ids of pending entries that turn out to have been
+    /// deleted from the stream, e.g. trimmed under `Retention`, when autoclaim
+    /// or recovery comes across them. They are acked before `f` is called.
+    pub fn on_deleted(mut self, f: impl Fn(&[String]) + Send + Sync + 'static) -> Self {
+        self.on_deleted = Some(Arc::new(f));
+        self
+    }
+
+    /// How often dequeues check for delayed items enqueued by other
+    /// processes, 1 second by default. Blocking reads wake this often to
+    /// check too. Items delayed through this stream are
+    /// promoted as soon as they are due regardless.
+    pub fn delayed_poll_interval(mut self, interval: Duration) -> Self {
+        self.delayed_poll_interval = interval;
+        self
+    }
+
     pub async fn build<I: Item>(self) -> Result<Stream<I>, Error> {
-        Stream::new(
-            &self.redis_connection_string,
-            self.stream_key,
-            self.queue_name,
-            self.consumer,
-            self.autoclaim_options,
-        )
-        .await
+        Stream::new(self).await
     }
 }
 
 impl<I: Item> Stream<I> {
-    async fn new(
-        redis_connection_string: &str,
-        stream_key: String,
-        queue_name: String,
-        consumer: String,
-        autoclaim_options: Option<AutoclaimOptions>,
-    ) -> Result<Self, Error> {
-        let redis = redis::Client::open(redis_connection_string)?;
-        let mut redis = redis::aio::ConnectionManager::new(redis).await?;
+    async fn new(builder: StreamBuilder) -> Result<Self, Error> {
+        let StreamBuilder {
+            redis_connection_string,
+            stream_key,
+            queue_name,
+            consumer,
+            autoclaim_options,
+            dead_letter,
+            parse_failure_policy,
+            atomic_batches,
+            retention,
+            delete_on_ack,
+            start_from,
+            recover_pending,
+            fill_batches,
+            on_deleted,
+            delayed_poll_interval,
+        } = builder;
+
+        let client = redis::Client::open(redis_connection_string)?;
+        let mut redis = redis::aio::ConnectionManager::new(client.clone()).await?;
 
         let queue_group_exists = if redis.exists(&stream_key).await? {
             let existing_groups: redis::streams::StreamInfoGroupsReply =
@@ -98,176 +858,1143 @@ impl<I: Item> Stream<I> {
 
         if !queue_group_exists {
             let _: () = redis
-                .xgroup_create_mkstream(&stream_key, &queue_name, "$")
+                .xgroup_create_mkstream(&stream_key, &queue_name, start_from.last_delivered_id())
                 .await?;
         }
 
-        let next_autoclaim = autoclaim_options.clone().map(|o| o.frequency);
+        let dequeue_stage = if recover_pending {
+            DequeueStage::Recover {
+                next_stream_id: "0-0".to_string(),
+            }
+        } else {
+            DequeueStage::read(autoclaim_options.as_ref())
+        };
+        let delayed_key = format!("{stream_key}:delayed");
+        let payloads_key = format!("{delayed_key}:payloads");
 
         let instance = Self {
             i: std::marker::PhantomData::default(),
             redis,
+            client,
+            blocking_connections: Arc::new(Mutex::new(vec![])),
             stream_key,
+            delayed_key,
+            payloads_key,
             queue_name,
             consumer,
             autoclaim_options,
-            dequeue_stage: DequeueStage::Read { next_autoclaim },
+            dequeue_stage: Arc::new(Mutex::new(dequeue_stage)),
+            delayed_schedule: Arc::new(Mutex::new(DelayedSchedule::new())),
+            delayed_poll_interval,
+            dead_letter,
+            parse_failure_policy,
+            parse_failures: Arc::new(Mutex::new(vec![])),
+            atomic_batches,
+            retention,
+            delete_on_ack,
+            fill_batches,
+            on_deleted,
         };
 
         Ok(instance)
     }
 
-    async fn read(
-        &mut self,
-        n: usize,
-        timeout: Option<std::time::Duration>,
-        next_autoclaim: &Option<usize>,
-    ) -> Result<Vec<I>, Error> {
-        let mut opts = redis::streams::StreamReadOptions::default()
-            .group(&self.queue_name, &self.consumer)
-            .count(n);
-
-        if let Some(timeout) = timeout {
-            opts = opts.block(timeout.as_millis() as usize);
-        }
+    /// List up to `count` items from the dead-letter stream, oldest first.
+    /// Returns nothing unless a dead-letter stream is configured.
+    pub async fn dead_letters(&self, count: usize) -> Result<Vec<DeadLetterItem<I>>, Error> {
+        let Some(DeadLetter::Stream(dead_letter_key)) = &self.dead_letter else {
+            return Ok(vec![]);
+        };
 
-        let res: redis::streams::StreamReadReply = self
+        let res: redis::streams::StreamRangeReply = self
             .redis
-            .xread_options(&[&self.stream_key], &[">"], &opts)
+            .clone()
+            .xrange_count(dead_letter_key, "-", "+", count)
             .await?;
 
-        if let Some(next_autoclaim) = next_autoclaim {
-            self.dequeue_stage = if *next_autoclaim <= 1 {
-                DequeueStage::Autoclaim {
-                    next_stream_id: "0-0".to_string(),
-                }
-            } else {
-                DequeueStage::Read {
-                    next_autoclaim: Some(next_autoclaim - 1),
-                }
-            }
-        }
+        res.ids.iter().map(DeadLetterItem::from_stream).collect()
+    }
 
-        if res.keys.is_empty() {
-            return Ok(vec![]);
+    /// Move entries from the dead-letter stream back into this stream,
+    /// returning how many were requeued.
+    pub async fn requeue_dead_letters(&self, ids: &[&str]) -> Result<usize, Error> {
+        let Some(DeadLetter::Stream(dead_letter_key)) = &self.dead_letter else {
+            return Ok(0);
+        };
+
+        if ids.is_empty() {
+            return Ok(0);
         }
 
-        let items = res.keys[0]
-            .ids
-            .iter()
-            .map(|i| I::from_stream(&i).ok_or_else(|| Error::ParseError(i.clone())))
-            .collect::<Result<Vec<I>, Error>>()?;
+        let requeued = REQUEUE_DEAD_LETTERS_SCRIPT
+            .key(dead_letter_key)
+            .key(&self.stream_key)
+            .arg(ids)
+            .invoke_async(&mut self.redis.clone())
+            .await?;
 
-        Ok(items)
+        Ok(requeued)
     }
 
-    async fn autoclaim(&mut self, n: usize, next_stream_id: &str) -> Result<Vec<I>, Error> {
-        let opts = redis::streams::StreamAutoClaimOptions::default().count(n);
+    /// List the consumer groups reading the stream.
+    pub async fn groups(&self) -> Result<Vec<redis::streams::StreamInfoGroup>, Error> {
+        let res: redis::streams::StreamInfoGroupsReply =
+            self.redis.clone().xinfo_groups(&self.stream_key).await?;
 
-        let res: redis::streams::StreamAutoClaimReply = self
+        Ok(res.groups)
+    }
+
+    /// Move this queue's consumer group to `start_from`, e.g. to replay the
+    /// stream or skip its backlog. Pending entries are left as they are.
+    pub async fn set_group_position(&self, start_from: StartFrom) -> Result<(), Error> {
+        let _: () = self
             .redis
-            .xautoclaim_options(
-                &self.stream_key,
-                &self.queue_name,
-                &self.consumer,
-                self.autoclaim_options
-                    .as_ref()
-                    .map(|o| o.min_idle_time.as_millis() as usize)
-                    .unwrap(),
-                next_stream_id,
-                opts,
-            )
+            .clone()
+            .xgroup_setid(&self.stream_key, &self.queue_name, start_from.last_delivered_id())
             .await?;
 
-        let items = res
-            .claimed
-            .into_iter()
-            .map(|i| I::from_stream(&i).ok_or_else(|| Error::ParseError(i.clone())))
-            .collect::<Result<Vec<I>, Error>>()?;
+        Ok(())
+    }
 
-        self.dequeue_stage = if res.next_stream_id == "0-0" {
-            DequeueStage::Read {
-                next_autoclaim: self.autoclaim_options.as_ref().map(|o| o.frequency),
-            }
-        } else {
-            DequeueStage::Autoclaim {
-                next_stream_id: res.next_stream_id,
-            }
-        };
+    /// Destroy this queue's consumer group along with its pending entries,
+    /// returning whether it existed. Dequeueing through any handle on the
+    /// group fails afterwards, until a new stream is built for it.
+    pub async fn destroy_group(&self) -> Result<bool, Error> {
+        let destroyed: usize = self
+            .redis
+            .clone()
+            .xgroup_destroy(&self.stream_key, &self.queue_name)
+            .await?;
 
-        Ok(items)
+        Ok(destroyed > 0)
     }
-}
 
-#[async_trait::async_trait]
-impl<I: Item + Send + Sync> Backend<I> for Stream<I> {
-    async fn enqueue(&mut self, item: &I) -> Result<(), crate::queue::error::Error> {
-        let item = item.to_stream();
-        let _: () = self.redis.xadd(&self.stream_key, "*", &item).await?;
+    /// Remove a consumer from this queue's group, returning the number of
+    /// pending entries it still held. Those entries are dropped from the
+    /// group's pending list, and are never redelivered.
+    pub async fn delete_consumer(&self, consumer: &str) -> Result<usize, Error> {
+        let pending = self
+            .redis
+            .clone()
+            .xgroup_delconsumer(&self.stream_key, &self.queue_name, consumer)
+            .await?;
 
-        Ok(())
+        Ok(pending)
     }
 
-    async fn dequeue(
-        &mut self,
-        n: usize,
-        timeout: Option<std::time::Duration>,
-    ) -> Result<Vec<I>, crate::queue::error::Error> {
-        match self.dequeue_stage.clone() {
-            DequeueStage::Read { next_autoclaim } => self.read(n, timeout, &next_autoclaim).await,
-            DequeueStage::Autoclaim { next_stream_id } => self.autoclaim(n, &next_stream_id).await,
+    /// Remove consumers of this queue's group that have been idle for at
+    /// least `min_idle_time` and hold no pending entries, returning their
+    /// names. This stream's own consumer is never removed.
+    pub async fn delete_idle_consumers(&self, min_idle_time: Duration) -> Result<Vec<String>, Error> {
+        let res: redis::streams::StreamInfoConsumersReply = self
+            .redis
+            .clone()
+            .xinfo_consumers(&self.stream_key, &self.queue_name)
+            .await?;
+
+        let mut deleted = vec![];
+        for consumer in res.consumers {
+            if consumer.name == self.consumer
+                || consumer.pending > 0
+                || (consumer.idle as u128) < min_idle_time.as_millis()
+            {
+                continue;
+            }
+
+            self.delete_consumer(&consumer.name).await?;
+            deleted.push(consumer.name);
         }
+
+        Ok(deleted)
     }
 
-    async fn ack(&mut self, items: &Vec<&I>) -> Result<(), crate::queue::error::Error> {
-        if items.is_empty() {
-            return Ok(());
-        }
+    /// Trim the stream according to its `Retention`, returning the number of
+    /// entries removed.
+    pub async fn trim(&self) -> Result<usize, Error> {
+        let Some(retention) = &self.retention else {
+            return Ok(0);
+        };
 
-        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
-        let _: () = self
+        let options = match retention {
+            Retention::MaxLen { len, approximate } => {
+                redis::streams::StreamTrimOptions::maxlen(trimming_mode(*approximate), *len)
+            }
+            Retention::MaxAge { age, approximate } => {
+                redis::streams::StreamTrimOptions::minid(trimming_mode(*approximate), min_id(*age))
+            }
+        };
+
+        let removed = self
             .redis
-            .xack(&self.stream_key, &self.queue_name, &ids)
+            .clone()
+            .xtrim_options(&self.stream_key, &options)
             .await?;
 
-        Ok(())
+        Ok(removed)
     }
 
-    async fn drop_items(
-        &mut self,
-        options: &DropOptions,
-    ) -> Result<Vec<super::DroppedItem>, crate::queue::error::Error> {
-        let min_idle_time = options.min_idle_time.as_millis() as u64;
-
-        let pending: Vec<(String, String, u64, u64)> = redis::cmd("XPENDING")
-            .arg(&self.stream_key)
-            .arg(&self.queue_name)
-            .arg("-")
-            .arg("+")
-            .arg(options.count)
-            .query_async(&mut self.redis)
-            .await?;
+    /// Spawn a task calling `trim` every `interval`, for streams whose
+    /// entries should expire even while nothing is enqueued. Errors are
+    /// ignored, the next tick retries. Abort the handle to stop trimming.
+    pub fn spawn_trim_task(&self, interval: Duration) -> tokio::task::JoinHandle<()>
+    where
+        I: Send + Sync + 'static,
+    {
+        let stream = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let _ = stream.trim().await;
+            }
+        })
+    }
 
-        let drop = pending
-            .into_iter()
-            .filter(|(_, _, idle, deliveries)| {
-                *idle > min_idle_time && *deliveries >= options.max_deliveries
-            })
-            .map(|(id, _, idle, deliveries)| DroppedItem {
-                id,
-                idle,
-                deliveries,
-            })
-            .collect::<Vec<DroppedItem>>();
+    /// `XADD` options applying the stream's `Retention`.
+    fn add_options(&self) -> redis::streams::StreamAddOptions {
+        let options = redis::streams::StreamAddOptions::default();
 
-        if !drop.is_empty() {
-            let drop_ids: Vec<&str> = drop.iter().map(|d| d.id.as_str()).collect();
+        match &self.retention {
+            Some(Retention::MaxLen { len, approximate }) => options.trim(
+                redis::streams::StreamTrimStrategy::maxlen(trimming_mode(*approximate), *len),
+            ),
+            Some(Retention::MaxAge { age, approximate }) => options.trim(
+                redis::streams::StreamTrimStrategy::minid(trimming_mode(*approximate), min_id(*age)),
+            ),
+            None => options,
+        }
+    }
+
+    /// Ack entries, deleting them if `delete_on_ack` is set.
+    async fn ack_ids(&self, ids: &[&str]) -> Result<(), Error> {
+        if self.delete_on_ack {
+            let _: usize = ACK_DELETE_SCRIPT
+                .key(&self.stream_key)
+                .arg(&self.queue_name)
+                .arg(ids)
+                .invoke_async(&mut self.redis.clone())
+                .await?;
+        } else {
             let _: () = self
                 .redis
-                .xack(&self.stream_key, &self.queue_name, &drop_ids)
+                .clone()
+                .xack(&self.stream_key, &self.queue_name, ids)
                 .await?;
         }
 
-        Ok(drop)
+        Ok(())
+    }
+
+    /// Delete entries already acked by a script, if `delete_on_ack` is set.
+    async fn delete_acked(&self, ids: &[&str]) -> Result<(), Error> {
+        if self.delete_on_ack {
+            self.ack_ids(ids).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Move `(id, deliveries, idle)` entries into another stream and ack them.
+    async fn dead_letter_into_stream(
+        &self,
+        dead_letter_key: &str,
+        entries: &[(&str, u64, u64)],
+    ) -> Result<(), Error> {
+        let mut invocation = DEAD_LETTER_SCRIPT.key(&self.stream_key);
+        invocation.key(dead_letter_key).arg(&self.queue_name);
+        for (id, deliveries, idle) in entries {
+            invocation.arg(id).arg(deliveries).arg(idle);
+        }
+
+        let _: () = invocation.invoke_async(&mut self.redis.clone()).await?;
+
+        let ids: Vec<&str> = entries.iter().map(|(id, _, _)| *id).collect();
+        self.delete_acked(&ids).await
+    }
+
+    /// Enqueue dropped items into the dead-letter backend with the same
+    /// fields as a dead-letter stream, then ack them.
+    async fn dead_letter_into_backend(
+        &self,
+        enqueue: &DeadLetterEnqueue,
+        drop: &[DroppedItem<I>],
+    ) -> Result<(), Error> {
+        let mut pipe = redis::pipe();
+        for d in drop.iter() {
+            pipe.xrange(&self.stream_key, &d.id, &d.id);
+        }
+
+        let entries: Vec<redis::streams::StreamRangeReply> =
+            pipe.query_async(&mut self.redis.clone()).await?;
+
+        for (d, entry) in drop.iter().zip(entries) {
+            // Entries that were trimmed or deleted have nothing left to move.
+            let Some(mut entry) = entry.ids.into_iter().next() else {
+                continue;
+            };
+
+            let metadata = [
+                self.stream_key.clone(),
+                entry.id.clone(),
+                self.queue_name.clone(),
+                d.deliveries.to_string(),
+                d.idle.to_string(),
+            ];
+            for (field, value) in DEAD_LETTER_FIELDS.into_iter().zip(metadata) {
+                entry
+                    .map
+                    .insert(field.to_string(), redis::Value::BulkString(value.into_bytes()));
+            }
+
+            enqueue(entry).await?;
+        }
+
+        let ids: Vec<&str> = drop.iter().map(|d| d.id.as_str()).collect();
+        self.ack_ids(&ids).await
+    }
+
+    /// Fill in the payloads of items about to be dropped.
+    async fn fetch_payloads(&self, drop: &mut [DroppedItem<I>]) -> Result<(), Error> {
+        let mut pipe = redis::pipe();
+        for d in drop.iter() {
+            pipe.xrange(&self.stream_key, &d.id, &d.id);
+        }
+
+        let entries: Vec<redis::streams::StreamRangeReply> =
+            pipe.query_async(&mut self.redis.clone()).await?;
+
+        for (d, entry) in drop.iter_mut().zip(entries) {
+            d.payload = entry.ids.into_iter().next().map(dropped_payload);
+        }
+
+        Ok(())
+    }
+
+    /// Move pending items into the dead letter, if any, and ack them,
+    /// returning those that were dropped.
+    async fn drop_pending(&self, drop: Vec<DroppedItem<I>>) -> Result<Vec<DroppedItem<I>>, Error> {
+        match self.dead_letter.clone() {
+            Some(DeadLetter::Stream(dead_letter_key)) => {
+                let entries: Vec<(&str, u64, u64)> = drop
+                    .iter()
+                    .map(|d| (d.id.as_str(), d.deliveries, d.idle))
+                    .collect();
+                self.dead_letter_into_stream(&dead_letter_key, &entries)
+                    .await?;
+
+                Ok(drop)
+            }
+            Some(DeadLetter::Backend(enqueue)) => {
+                self.dead_letter_into_backend(&enqueue, &drop).await?;
+
+                Ok(drop)
+            }
+            None => {
+                let drop_ids: Vec<&str> = drop.iter().map(|d| d.id.as_str()).collect();
+                self.ack_ids(&drop_ids).await?;
+
+                Ok(drop)
+            }
+        }
+    }
+
+    /// Promote due delayed items into the stream if any may be due, returning
+    /// when to check again: once the next delayed item known of becomes due,
+    /// or after the poll interval for items delayed elsewhere.
+    async fn promote_delayed(&self) -> Result<SystemTime, Error> {
+        let now = SystemTime::now();
+        {
+            let schedule = self.delayed_schedule.lock().unwrap();
+            if !schedule.check_due(now) {
+                return Ok(schedule.check_at());
+            }
+        }
+
+        let next_due: Option<f64> = PROMOTE_DELAYED_SCRIPT
+            .key(&self.stream_key)
+            .key(&self.delayed_key)
+            .key(&self.payloads_key)
+            .arg(unix_millis(now))
+            .arg(PROMOTE_DELAYED_LIMIT)
+            .arg(&trim_args(self.retention.as_ref()))
+            .invoke_async(&mut self.redis.clone())
+            .await?;
+
+        let next_due = next_due.map(|ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms as u64));
+        let mut schedule = self.delayed_schedule.lock().unwrap();
+        schedule.promoted(next_due, self.delayed_poll_interval);
+
+        Ok(schedule.check_at())
+    }
+
+    async fn read(
+        &self,
+        n: usize,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let mut opts = redis::streams::StreamReadOptions::default()
+            .group(&self.queue_name, &self.consumer)
+            .count(n);
+
+        let mut res: redis::streams::StreamReadReply = match timeout {
+            Some(timeout) => {
+                opts = opts.block(timeout.as_millis() as usize);
+                self.read_blocking_connection(&opts).await?
+            }
+            None => {
+                self.redis
+                    .clone()
+                    .xread_options(&[&self.stream_key], &[">"], &opts)
+                    .await?
+            }
+        };
+
+        if res.keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let entries = res
+            .keys
+            .swap_remove(0)
+            .ids
+            .into_iter()
+            .map(|i| (i, 1, Duration::ZERO, DeliverySource::Read))
+            .collect();
+
+        self.parse_entries(entries).await
+    }
+
+    /// Run a blocking `XREADGROUP` on a connection of its own, reusing one a
+    /// previous blocking read returned if there is one. Connections that
+    /// errored, or whose read was abandoned, are dropped rather than reused.
+    async fn read_blocking_connection(
+        &self,
+        opts: &redis::streams::StreamReadOptions,
+    ) -> Result<redis::streams::StreamReadReply, Error> {
+        let redis = self.blocking_connections.lock().unwrap().pop();
+        let mut redis = match redis {
+            Some(redis) => redis,
+            None => self.client.get_multiplexed_async_connection().await?,
+        };
+
+        let res = redis
+            .xread_options(&[&self.stream_key], &[">"], opts)
+            .await?;
+        self.blocking_connections.lock().unwrap().push(redis);
+
+        Ok(res)
+    }
+
+    /// Reread up to `n` of this consumer's pending entries after
+    /// `next_stream_id`, moving on to reading new entries once none are left.
+    /// Entries deleted from the stream while pending are acked and reported.
+    async fn recover(
+        &self,
+        n: usize,
+        next_stream_id: &str,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        // Hand the cursor back unless this recovery completes.
+        let guard = StageGuard::new(
+            &self.dequeue_stage,
+            DequeueStage::Recover {
+                next_stream_id: next_stream_id.to_string(),
+            },
+        );
+
+        let (pending, mut res): (
+            Vec<(String, String, u64, u64)>,
+            redis::streams::StreamReadReply,
+        ) = RECOVER_SCRIPT
+            .key(&self.stream_key)
+            .arg(&self.queue_name)
+            .arg(&self.consumer)
+            .arg(next_stream_id)
+            .arg(n)
+            .invoke_async(&mut self.redis.clone())
+            .await?;
+
+        let pending: HashMap<String, (u64, u64)> = pending
+            .into_iter()
+            .map(|(id, _, idle, deliveries)| (id, (idle, deliveries)))
+            .collect();
+
+        let ids = if res.keys.is_empty() {
+            vec![]
+        } else {
+            res.keys.swap_remove(0).ids
+        };
+
+        guard.complete(match ids.last() {
+            Some(last) if ids.len() >= n => DequeueStage::Recover {
+                next_stream_id: last.id.clone(),
+            },
+            _ => DequeueStage::read(self.autoclaim_options.as_ref()),
+        });
+
+        let (deleted, ids): (Vec<_>, Vec<_>) = ids.into_iter().partition(|i| i.is_empty());
+        let deleted: Vec<String> = deleted.into_iter().map(|i| i.id).collect();
+        self.clean_deleted(&deleted).await?;
+
+        let entries = ids
+            .into_iter()
+            .map(|i| {
+                let (idle, deliveries) = pending.get(&i.id).copied().unwrap_or_default();
+                (
+                    i,
+                    deliveries + 1,
+                    Duration::from_millis(idle),
+                    DeliverySource::Recovered,
+                )
+            })
+            .collect();
+
+        self.parse_entries(entries).await
+    }
+
+    async fn autoclaim(
+        &self,
+        n: usize,
+        next_stream_id: &str,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        // Hand the cursor back unless this autoclaim completes.
+        let guard = StageGuard::new(
+            &self.dequeue_stage,
+            DequeueStage::Autoclaim {
+                next_stream_id: next_stream_id.to_string(),
+            },
+        );
+
+        let (pending, res): (
+            Vec<(String, String, u64, u64)>,
+            redis::streams::StreamAutoClaimReply,
+        ) = AUTOCLAIM_SCRIPT
+            .key(&self.stream_key)
+            .arg(&self.queue_name)
+            .arg(&self.consumer)
+            .arg(
+                self.autoclaim_options
+                    .as_ref()
+                    .map(|o| o.min_idle_time.as_millis() as usize)
+                    .unwrap(),
+            )
+            .arg(next_stream_id)
+            .arg(n)
+            .invoke_async(&mut self.redis.clone())
+            .await?;
+
+        let pending: HashMap<String, (u64, u64)> = pending
+            .into_iter()
+            .map(|(id, _, idle, deliveries)| (id, (idle, deliveries)))
+            .collect();
+
+        let entries = res
+            .claimed
+            .into_iter()
+            .map(|i| {
+                let (idle, deliveries) = pending.get(&i.id).copied().unwrap_or_default();
+                (
+                    i,
+                    deliveries + 1,
+                    Duration::from_millis(idle),
+                    DeliverySource::Autoclaim,
+                )
+            })
+            .collect();
+
+        guard.complete(if res.next_stream_id == "0-0" {
+            DequeueStage::read(self.autoclaim_options.as_ref())
+        } else {
+            DequeueStage::Autoclaim {
+                next_stream_id: res.next_stream_id,
+            }
+        });
+
+        self.clean_deleted(&res.deleted_ids).await?;
+
+        self.parse_entries(entries).await
+    }
+
+    /// Ack pending entries that were deleted from the stream, which can't be
+    /// delivered again, and report them to the `on_deleted` callback.
+    async fn clean_deleted(&self, ids: &[String]) -> Result<(), Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let _: () = self
+            .redis
+            .clone()
+            .xack(&self.stream_key, &self.queue_name, ids)
+            .await?;
+
+        if let Some(on_deleted) = &self.on_deleted {
+            on_deleted(ids);
+        }
+
+        Ok(())
+    }
+
+    /// Read new entries, blocking for up to `timeout` as `XREADGROUP` does.
+    /// The block wakes at `check_delayed_at` and every poll interval after to
+    /// promote delayed items, which are then read within the same call, while
+    /// an autoclaim becoming due at `autoclaim_at` ends it early.
+    async fn read_blocking(
+        &self,
+        n: usize,
+        timeout: Option<Duration>,
+        mut check_delayed_at: SystemTime,
+        autoclaim_at: Option<Instant>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let deadline = timeout
+            .filter(|t| !t.is_zero())
+            .map(|t| Instant::now() + t);
+
+        loop {
+            let mut block = match deadline {
+                Some(deadline) => cap_timeout(timeout, deadline.saturating_duration_since(Instant::now())),
+                None => timeout,
+            };
+
+            // Don't block past the point where delayed items need promoting,
+            // whether one becomes due or the poll interval is up, nor past the
+            // point where an autoclaim becomes due.
+            let until = check_delayed_at.duration_since(SystemTime::now()).unwrap_or_default();
+            block = cap_timeout(block, until);
+            if let Some(at) = autoclaim_at {
+                block = cap_timeout(block, at.saturating_duration_since(Instant::now()));
+            }
+
+            let items = self.read(n, block).await?;
+
+            let now = Instant::now();
+            if !items.is_empty()
+                || timeout.is_none()
+                || deadline.is_some_and(|deadline| deadline <= now)
+                || autoclaim_at.is_some_and(|at| at <= now)
+            {
+                return Ok(items);
+            }
+
+            check_delayed_at = self.promote_delayed().await?;
+        }
+    }
+
+    /// Top up reclaimed `items` with new entries if `fill_batches` is set,
+    /// only blocking for `timeout` when nothing was reclaimed.
+    async fn fill_batch(
+        &self,
+        mut items: Vec<Delivery<I>>,
+        n: usize,
+        timeout: Option<Duration>,
+        check_delayed_at: SystemTime,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        if !self.fill_batches || items.len() >= n {
+            return Ok(items);
+        }
+
+        if items.is_empty() {
+            return self.read_blocking(n, timeout, check_delayed_at, None).await;
+        }
+
+        items.extend(self.read(n - items.len(), None).await?);
+
+        Ok(items)
+    }
+
+    /// Parse dequeued entries, handling those that fail according to the
+    /// configured `ParseFailurePolicy`.
+    async fn parse_entries(
+        &self,
+        entries: Vec<(redis::streams::StreamId, u64, Duration, DeliverySource)>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let mut items = vec![];
+        let mut failures = vec![];
+
+        for (entry, deliveries, idle, source) in entries {
+            match I::from_stream(&entry) {
+                Ok(item) => items.push(Delivery {
+                    item,
+                    deliveries: prior_attempts(&entry) + deliveries,
+                    idle,
+                    enqueued_at: stream_id_time(&entry.id),
+                    source,
+                }),
+                Err(e) => failures.push((entry, deliveries, idle, e)),
+            }
+        }
+
+        if failures.is_empty() {
+            return Ok(items);
+        }
+
+        match self.parse_failure_policy.clone() {
+            ParseFailurePolicy::Error => {
+                let (entry, _, _, e) = failures.swap_remove(0);
+                return Err(Error::ParseError(entry, Box::new(e)));
+            }
+            ParseFailurePolicy::Skip => {
+                let ids: Vec<&str> = failures.iter().map(|(e, _, _, _)| e.id.as_str()).collect();
+                self.ack_ids(&ids).await?;
+            }
+            ParseFailurePolicy::Quarantine(quarantine_key) => {
+                let entries: Vec<(&str, u64, u64)> = failures
+                    .iter()
+                    .map(|(e, deliveries, idle, _)| {
+                        (e.id.as_str(), *deliveries, idle.as_millis() as u64)
+                    })
+                    .collect();
+                self.dead_letter_into_stream(&quarantine_key, &entries)
+                    .await?;
+            }
+            ParseFailurePolicy::Collect => {
+                self.parse_failures
+                    .lock()
+                    .unwrap()
+                    .extend(failures.into_iter().map(|(e, _, _, _)| e));
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Take the entries that failed to parse under `ParseFailurePolicy::Collect`.
+    pub fn take_parse_failures(&self) -> Vec<redis::streams::StreamId> {
+        std::mem::take(&mut *self.parse_failures.lock().unwrap())
+    }
+}
+
+#[async_trait::async_trait]
+impl<I: Item + Send + Sync> SharedBackend<I> for Stream<I> {
+    type Id = String;
+
+    async fn enqueue(&self, item: &I) -> Result<String, Error> {
+        let item = item.to_stream()?;
+        let id = self
+            .redis
+            .clone()
+            .xadd_options(&self.stream_key, "*", &item, &self.add_options())
+            .await?;
+
+        Ok(id)
+    }
+
+    async fn enqueue_at(&self, item: &I, at: SystemTime) -> Result<(), Error> {
+        if at <= SystemTime::now() {
+            return SharedBackend::enqueue(self, item).await.map(|_| ());
+        }
+
+        let invocation = enqueue_delayed(&self.delayed_key, &self.payloads_key, item, at)?;
+        let _: () = invocation.invoke_async(&mut self.redis.clone()).await?;
+        self.delayed_schedule.lock().unwrap().delayed(at);
+
+        Ok(())
+    }
+
+    /// Adds all items with a single pipelined round trip, atomically if
+    /// `StreamBuilder::atomic_batches` is set.
+    async fn enqueue_batch(&self, items: &[&I]) -> Result<Vec<String>, Error> {
+        if items.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut pipe = redis::pipe();
+        if self.atomic_batches {
+            pipe.atomic();
+        }
+
+        let options = self.add_options();
+        for item in items {
+            pipe.xadd_options(&self.stream_key, "*", &item.to_stream()?, &options);
+        }
+
+        let ids = pipe.query_async(&mut self.redis.clone()).await?;
+
+        Ok(ids)
+    }
+
+    async fn dequeue(
+        &self,
+        n: usize,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<I>, Error> {
+        let deliveries = self.dequeue_deliveries(n, timeout).await?;
+        Ok(deliveries.into_iter().map(|d| d.item).collect())
+    }
+
+    async fn dequeue_deliveries(
+        &self,
+        n: usize,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let check_delayed_at = self.promote_delayed().await?;
+
+        let step = self.dequeue_stage.lock().unwrap().claim();
+        match step {
+            DequeueStep::Read { autoclaim_at } => {
+                self.read_blocking(n, timeout, check_delayed_at, autoclaim_at).await
+            }
+            DequeueStep::Recover(next_stream_id) => {
+                let recovered = self.recover(n, &next_stream_id).await?;
+                if recovered.is_empty() {
+                    return self.read_blocking(n, timeout, check_delayed_at, None).await;
+                }
+
+                self.fill_batch(recovered, n, timeout, check_delayed_at).await
+            }
+            DequeueStep::Autoclaim(next_stream_id) => {
+                let claimed = self.autoclaim(n, &next_stream_id).await?;
+                self.fill_batch(claimed, n, timeout, check_delayed_at).await
+            }
+        }
+    }
+
+    async fn ack(&self, items: &[&I]) -> Result<(), Error> {
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
+        self.ack_ids(&ids).await
+    }
+
+    /// Nacked items are re-added to the stream as new entries, carrying their
+    /// delivery count over. Other consumer groups reading the same stream will
+    /// see them again.
+    async fn nack(
+        &self,
+        items: &[&I],
+        requeue_delay: Option<Duration>,
+    ) -> Result<(), Error> {
+        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let at = requeue_delay.map(due_in).transpose()?;
+
+        let _: () = NACK_SCRIPT
+            .key(&self.stream_key)
+            .key(&self.delayed_key)
+            .key(&self.payloads_key)
+            .arg(&self.queue_name)
+            .arg(&self.consumer)
+            .arg(at.map(unix_millis).unwrap_or(0))
+            .arg(&trim_args(self.retention.as_ref()))
+            .arg(&ids)
+            .invoke_async(&mut self.redis.clone())
+            .await?;
+
+        if let Some(at) = at {
+            self.delayed_schedule.lock().unwrap().delayed(at);
+        }
+
+        self.delete_acked(&ids).await
+    }
+
+    /// Moves the items into the configured dead letter, if any, and acks them.
+    async fn dead_letter(&self, items: &[&I]) -> Result<(), Error> {
+        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let invocations: Vec<redis::ScriptInvocation> = ids
+            .iter()
+            .map(|id| pending_entry(&self.stream_key, &self.queue_name, id))
+            .collect();
+
+        let mut pipe = redis::pipe();
+        for invocation in invocations.iter() {
+            pipe.invoke_script(invocation);
+        }
+
+        let pending: Vec<Vec<(String, String, u64, u64)>> =
+            pipe.query_async(&mut self.redis.clone()).await?;
+
+        let drop = pending
+            .into_iter()
+            .flatten()
+            .map(|(id, _, idle, deliveries)| DroppedItem {
+                id,
+                idle,
+                deliveries,
+                payload: None,
+            })
+            .collect::<Vec<DroppedItem<I>>>();
+
+        if !drop.is_empty() {
+            self.drop_pending(drop).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Pages through the pending entries list, dropping matching items a page
+    /// at a time.
+    async fn drop_items(
+        &self,
+        options: &DropOptions,
+    ) -> Result<Vec<DroppedItem<I>>, Error> {
+        let mut dropped = vec![];
+        let mut scanned = 0;
+        let mut start = "-".to_string();
+
+        loop {
+            let count = match options.scan_limit {
+                Some(scan_limit) => options.count.min(scan_limit - scanned),
+                None => options.count,
+            };
+            if count == 0 {
+                break;
+            }
+
+            let pending: Vec<(String, String, u64, u64)> =
+                pending_page(&self.stream_key, &self.queue_name, &start, count, options)
+                    .invoke_async(&mut self.redis.clone())
+                    .await?;
+
+            scanned += pending.len() as u64;
+            let exhausted = (pending.len() as u64) < count;
+            if let Some((last, _, _, _)) = pending.last() {
+                start = format!("({last}");
+            }
+
+            let mut drop = droppable(pending, options);
+            if !drop.is_empty() {
+                if options.payloads {
+                    self.fetch_payloads(&mut drop).await?;
+                }
+
+                dropped.extend(self.drop_pending(drop).await?);
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(dropped)
+    }
+}
+
+#[async_trait::async_trait]
+impl<I: Item + Send + Sync> Backend<I> for Stream<I> {
+    type Id = String;
+
+    async fn enqueue(&mut self, item: &I) -> Result<String, Error> {
+        SharedBackend::enqueue(self, item).await
+    }
+
+    async fn enqueue_at(&mut self, item: &I, at: SystemTime) -> Result<(), Error> {
+        SharedBackend::enqueue_at(self, item, at).await
+    }
+
+    async fn enqueue_batch(&mut self, items: &[&I]) -> Result<Vec<String>, Error> {
+        SharedBackend::enqueue_batch(self, items).await
+    }
+
+    async fn dequeue(&mut self, n: usize, timeout: Option<Duration>) -> Result<Vec<I>, Error> {
+        SharedBackend::dequeue(self, n, timeout).await
+    }
+
+    async fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        SharedBackend::dequeue_deliveries(self, n, timeout).await
+    }
+
+    async fn ack(&mut self, items: &Vec<&I>) -> Result<(), Error> {
+        SharedBackend::ack(self, items).await
+    }
+
+    async fn nack(&mut self, items: &[&I], requeue_delay: Option<Duration>) -> Result<(), Error> {
+        SharedBackend::nack(self, items, requeue_delay).await
+    }
+
+    async fn dead_letter(&mut self, items: &[&I]) -> Result<(), Error> {
+        SharedBackend::dead_letter(self, items).await
+    }
+
+    async fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error> {
+        SharedBackend::drop_items(self, options).await
+    }
+}
+
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// An `ENQUEUE_DELAYED` invocation adding `item` to the delayed set, due at `at`.
+pub(crate) fn enqueue_delayed<'a, I: Item>(
+    delayed_key: &str,
+    payloads_key: &str,
+    item: &I,
+    at: SystemTime,
+) -> Result<redis::ScriptInvocation<'a>, Error> {
+    let mut invocation = ENQUEUE_DELAYED_SCRIPT.key(delayed_key);
+    invocation
+        .key(payloads_key)
+        .arg(uuid::Uuid::new_v4().to_string())
+        .arg(unix_millis(at));
+
+    for (field, value) in item.to_stream()? {
+        invocation.arg(field).arg(value);
+    }
+
+    Ok(invocation)
+}
+
+/// Cap a blocking dequeue `timeout`, where zero blocks indefinitely, so that
+/// it returns within `until`.
+pub(crate) fn cap_timeout(timeout: Option<Duration>, until: Duration) -> Option<Duration> {
+    let until = until.max(Duration::from_millis(1));
+
+    match timeout {
+        Some(timeout) if timeout.is_zero() => Some(until),
+        Some(timeout) => Some(timeout.min(until)),
+        None => None,
+    }
+}
+
+/// `XADD` trim arguments (strategy, mode and threshold) applying `retention`
+/// in scripts, all empty without one.
+pub(crate) fn trim_args(retention: Option<&Retention>) -> [String; 3] {
+    let mode = |approximate: bool| if approximate { "~" } else { "=" }.to_string();
+
+    match retention {
+        Some(Retention::MaxLen { len, approximate }) => {
+            ["MAXLEN".to_string(), mode(*approximate), len.to_string()]
+        }
+        Some(Retention::MaxAge { age, approximate }) => {
+            ["MINID".to_string(), mode(*approximate), min_id(*age)]
+        }
+        None => Default::default(),
+    }
+}
+
+fn trimming_mode(approximate: bool) -> redis::streams::StreamTrimmingMode {
+    if approximate {
+        redis::streams::StreamTrimmingMode::Approx
+    } else {
+        redis::streams::StreamTrimmingMode::Exact
+    }
+}
+
+/// The smallest stream id to keep under `Retention::MaxAge`.
+fn min_id(age: Duration) -> String {
+    let cutoff = SystemTime::now().checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+    format!("{}-0", unix_millis(cutoff))
+}
+
+/// `PENDING` for up to `count` entries from `start` that are idle for at
+/// least `options.min_idle_time`, and pending for `options.consumer` if set.
+pub(crate) fn pending_page<'a>(
+    stream_key: &str,
+    queue_name: &str,
+    start: &str,
+    count: u64,
+    options: &DropOptions,
+) -> redis::ScriptInvocation<'a> {
+    let mut invocation = PENDING_SCRIPT.key(stream_key);
+    invocation
+        .arg(queue_name)
+        .arg("IDLE")
+        .arg(options.min_idle_time.as_millis() as u64)
+        .arg(start)
+        .arg("+")
+        .arg(count);
+
+    if let Some(consumer) = &options.consumer {
+        invocation.arg(consumer);
+    }
+
+    invocation
+}
+
+/// `PENDING` for the single entry `id`.
+fn pending_entry<'a>(stream_key: &str, queue_name: &str, id: &str) -> redis::ScriptInvocation<'a> {
+    let mut invocation = PENDING_SCRIPT.key(stream_key);
+    invocation.arg(queue_name).arg(id).arg(id).arg(1);
+    invocation
+}
+
+/// The pending entries that `options` drops, without their payloads.
+pub(crate) fn droppable<I>(
+    pending: Vec<(String, String, u64, u64)>,
+    options: &DropOptions,
+) -> Vec<DroppedItem<I>> {
+    let min_idle_time = options.min_idle_time.as_millis() as u64;
+
+    pending
+        .into_iter()
+        .filter(|(_, _, idle, deliveries)| {
+            *idle > min_idle_time && *deliveries >= options.max_deliveries
+        })
+        .map(|(id, _, idle, deliveries)| DroppedItem {
+            id,
+            idle,
+            deliveries,
+            payload: None,
+        })
+        .collect()
+}
+
+/// Entry field holding its deliveries before it was last nacked, see `NACK`.
+pub(crate) const ATTEMPTS_FIELD: &str = "rdq-attempts";
+
+pub(crate) fn prior_attempts(entry: &redis::streams::StreamId) -> u64 {
+    entry.get(ATTEMPTS_FIELD).unwrap_or_default()
+}
+
+/// The payload of a dropped entry, parsed if possible.
+pub(crate) fn dropped_payload<I: Item>(entry: redis::streams::StreamId) -> DroppedPayload<I> {
+    match I::from_stream(&entry) {
+        Ok(item) => DroppedPayload::Item(item),
+        Err(_) => DroppedPayload::Raw(entry.map),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::stream::{DequeueStage, DequeueStep, StageGuard};
+    use std::sync::Mutex;
+
+    fn autoclaim(next_stream_id: &str) -> DequeueStage {
+        DequeueStage::Autoclaim {
+            next_stream_id: next_stream_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn dropped_steps_hand_the_stage_back() {
+        let stage = Mutex::new(autoclaim("1-0"));
+
+        let DequeueStep::Autoclaim(next_stream_id) = stage.lock().unwrap().claim() else {
+            panic!("expected an autoclaim");
+        };
+        assert_eq!(matches!(*stage.lock().unwrap(), DequeueStage::Autoclaiming), true);
+
+        // e.g. the dequeue was dropped during the script round trip
+        drop(StageGuard::new(&stage, autoclaim(&next_stream_id)));
+        assert_eq!(
+            matches!(&*stage.lock().unwrap(), DequeueStage::Autoclaim { next_stream_id } if next_stream_id == "1-0"),
+            true
+        );
+    }
+
+    #[test]
+    fn completed_steps_move_the_stage_on() {
+        let stage = Mutex::new(autoclaim("1-0"));
+
+        let DequeueStep::Autoclaim(next_stream_id) = stage.lock().unwrap().claim() else {
+            panic!("expected an autoclaim");
+        };
+
+        StageGuard::new(&stage, autoclaim(&next_stream_id)).complete(DequeueStage::read(None));
+        assert_eq!(matches!(*stage.lock().unwrap(), DequeueStage::Read { .. }), true);
     }
 }
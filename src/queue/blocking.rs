@@ -0,0 +1,110 @@
+//! Synchronous counterparts of `Backend` and `Queue`, for use outside of an
+//! async runtime.
+
+pub mod stream;
+
+use crate::queue::backend::{DropOptions, DroppedItem, due_in};
+use crate::queue::delivery::Delivery;
+use crate::queue::error::Error;
+
+pub trait SyncBackend<I> {
+    /// Identifies an enqueued item, e.g. its stream id.
+    type Id;
+
+    fn enqueue(&mut self, item: &I) -> Result<Self::Id, Error>;
+    fn enqueue_at(&mut self, item: &I, at: std::time::SystemTime) -> Result<(), Error>;
+    fn dequeue(&mut self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<I>, Error>;
+    fn dequeue_deliveries(&mut self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<Delivery<I>>, Error>;
+    fn ack(&mut self, items: &[&I]) -> Result<(), Error>;
+    fn nack(&mut self, items: &[&I], requeue_delay: Option<std::time::Duration>) -> Result<(), Error>;
+    fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error>;
+}
+
+#[derive(Clone)]
+pub struct SyncQueue<I, B: SyncBackend<I>> {
+    i: std::marker::PhantomData<I>,
+    backend: B
+}
+
+impl<I, B: SyncBackend<I>> SyncQueue<I, B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            i: std::marker::PhantomData,
+            backend
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Enqueue an item, returning its id.
+    pub fn enqueue(
+        &mut self,
+        item: &I
+    ) -> Result<B::Id, Error> {
+        self.backend.enqueue(item)
+    }
+
+    /// Enqueue an item that only becomes available for dequeue at `at`.
+    pub fn enqueue_at(
+        &mut self,
+        item: &I,
+        at: std::time::SystemTime
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, at)
+    }
+
+    /// Enqueue an item that only becomes available for dequeue after `delay`.
+    pub fn enqueue_in(
+        &mut self,
+        item: &I,
+        delay: std::time::Duration
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, due_in(delay)?)
+    }
+
+    pub fn dequeue(
+        &mut self,
+        n: usize,
+        timeout: Option<std::time::Duration>
+    ) -> Result<Vec<I>, Error> {
+        self.backend.dequeue(n, timeout)
+    }
+
+    /// Dequeue items along with their delivery metadata.
+    pub fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<std::time::Duration>
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        self.backend.dequeue_deliveries(n, timeout)
+    }
+
+    pub fn ack(
+        &mut self,
+        items: &[&I]
+    ) -> Result<(), Error> {
+        self.backend.ack(items)
+    }
+
+    /// Return items to the queue, either immediately or after `requeue_delay`.
+    pub fn nack(
+        &mut self,
+        items: &[&I],
+        requeue_delay: Option<std::time::Duration>
+    ) -> Result<(), Error> {
+        self.backend.nack(items, requeue_delay)
+    }
+
+    pub fn drop_items(
+        &mut self,
+        options: &DropOptions
+    ) -> Result<Vec<DroppedItem<I>>, Error> {
+        self.backend.drop_items(options)
+    }
+}
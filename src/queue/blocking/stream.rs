@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use redis::Commands;
+
+use crate::queue::backend::stream::{
+    AUTOCLAIM_SCRIPT, AutoclaimOptions, DEAD_LETTER_SCRIPT, DELAYED_POLL_INTERVAL, DelayedSchedule,
+    DequeueStage, DequeueStep, NACK_SCRIPT, PROMOTE_DELAYED_LIMIT, PROMOTE_DELAYED_SCRIPT, StageGuard,
+    cap_timeout, droppable, dropped_payload, enqueue_delayed, pending_page, prior_attempts, trim_args,
+    unix_millis,
+};
+use crate::queue::backend::{DropOptions, DroppedItem, due_in};
+use crate::queue::blocking::SyncBackend;
+use crate::queue::delivery::{Delivery, DeliverySource, stream_id_time};
+use crate::queue::error::Error;
+use crate::queue::item::Item;
+
+/// A synchronous stream backend, sharing its stream layout (delayed items,
+/// dead-letter fields) with the async `Stream`. Connections are checked out
+/// of an r2d2 pool per operation, and clones share their autoclaim cursor
+/// and delayed schedule, so they can be used from several threads.
+///
+/// Entries that fail to parse fail the dequeue with `Error::ParseError`.
+#[derive(Clone)]
+pub struct Stream<I: Item> {
+    i: std::marker::PhantomData<I>,
+    pool: r2d2::Pool<redis::Client>,
+    stream_key: String,
+    delayed_key: String,
+    payloads_key: String,
+    queue_name: String,
+    consumer: String,
+    autoclaim_options: Option<AutoclaimOptions>,
+    dequeue_stage: Arc<Mutex<DequeueStage>>,
+    delayed_schedule: Arc<Mutex<DelayedSchedule>>,
+    delayed_poll_interval: Duration,
+    dead_letter_stream: Option<String>,
+}
+
+pub struct StreamBuilder {
+    redis_connection_string: String,
+    stream_key: String,
+    queue_name: String,
+    consumer: String,
+    autoclaim_options: Option<AutoclaimOptions>,
+    dead_letter_stream: Option<String>,
+    pool_size: u32,
+    delayed_poll_interval: Duration,
+}
+
+impl StreamBuilder {
+    /// Initialize a stream builder, with a random consumer (V4 UUID).
+    pub fn new(
+        redis_connection_string: impl Into<String>,
+        stream_key: impl Into<String>,
+        queue_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            redis_connection_string: redis_connection_string.into(),
+            stream_key: stream_key.into(),
+            queue_name: queue_name.into(),
+            consumer: uuid::Uuid::new_v4().to_string(),
+            autoclaim_options: None,
+            dead_letter_stream: None,
+            pool_size: 10,
+            delayed_poll_interval: DELAYED_POLL_INTERVAL,
+        }
+    }
+
+    pub fn consumer(mut self, consumer: impl Into<String>) -> Self {
+        self.consumer = consumer.into();
+        self
+    }
+
+    pub fn autoclaim_options(mut self, options: AutoclaimOptions) -> Self {
+        self.autoclaim_options = Some(options);
+        self
+    }
+
+    /// Move items dropped by `drop_items` into another stream instead of
+    /// discarding them.
+    pub fn dead_letter_stream(mut self, stream_key: impl Into<String>) -> Self {
+        self.dead_letter_stream = Some(stream_key.into());
+        self
+    }
+
+    /// Maximum number of pooled connections, 10 by default.
+    pub fn pool_size(mut self, pool_size: u32) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    /// How often dequeues check for delayed items enqueued by other
+    /// processes, 1 second by default. Blocking reads wake this often to
+    /// check too.
+    pub fn delayed_poll_interval(mut self, interval: Duration) -> Self {
+        self.delayed_poll_interval = interval;
+        self
+    }
+
+    pub fn build<I: Item>(self) -> Result<Stream<I>, Error> {
+        Stream::new(self)
+    }
+}
+
+impl<I: Item> Stream<I> {
+    fn new(builder: StreamBuilder) -> Result<Self, Error> {
+        let StreamBuilder {
+            redis_connection_string,
+            stream_key,
+            queue_name,
+            consumer,
+            autoclaim_options,
+            dead_letter_stream,
+            pool_size,
+            delayed_poll_interval,
+        } = builder;
+
+        let client = redis::Client::open(redis_connection_string)?;
+        let pool = r2d2::Pool::builder().max_size(pool_size).build(client)?;
+
+        let mut instance =
+            Self::from_pool(pool, stream_key, queue_name, consumer, autoclaim_options, dead_letter_stream)?;
+        instance.delayed_poll_interval = delayed_poll_interval;
+
+        Ok(instance)
+    }
+
+    /// Build a stream on an existing pool, e.g. one shared with other code.
+    pub fn from_pool(
+        pool: r2d2::Pool<redis::Client>,
+        stream_key: impl Into<String>,
+        queue_name: impl Into<String>,
+        consumer: impl Into<String>,
+        autoclaim_options: Option<AutoclaimOptions>,
+        dead_letter_stream: Option<String>,
+    ) -> Result<Self, Error> {
+        let stream_key = stream_key.into();
+        let queue_name = queue_name.into();
+        let mut redis = pool.get()?;
+
+        let queue_group_exists = if redis.exists(&stream_key)? {
+            let existing_groups: redis::streams::StreamInfoGroupsReply =
+                redis.xinfo_groups(&stream_key)?;
+            existing_groups.groups.iter().any(|g| g.name == queue_name)
+        } else {
+            false
+        };
+
+        if !queue_group_exists {
+            let _: () = redis.xgroup_create_mkstream(&stream_key, &queue_name, "$")?;
+        }
+
+        let delayed_key = format!("{stream_key}:delayed");
+        let payloads_key = format!("{delayed_key}:payloads");
+        let dequeue_stage = DequeueStage::read(autoclaim_options.as_ref());
+
+        let instance = Self {
+            i: std::marker::PhantomData,
+            pool,
+            stream_key,
+            delayed_key,
+            payloads_key,
+            queue_name,
+            consumer: consumer.into(),
+            autoclaim_options,
+            dequeue_stage: Arc::new(Mutex::new(dequeue_stage)),
+            delayed_schedule: Arc::new(Mutex::new(DelayedSchedule::new())),
+            delayed_poll_interval: DELAYED_POLL_INTERVAL,
+            dead_letter_stream,
+        };
+
+        Ok(instance)
+    }
+
+    pub fn pool(&self) -> &r2d2::Pool<redis::Client> {
+        &self.pool
+    }
+
+    /// Promote due delayed items into the stream if any may be due, returning
+    /// when to check again: once the next delayed item known of becomes due,
+    /// or after the poll interval for items delayed elsewhere.
+    fn promote_delayed(&self, redis: &mut redis::Connection) -> Result<SystemTime, Error> {
+        let now = SystemTime::now();
+        {
+            let schedule = self.delayed_schedule.lock().unwrap();
+            if !schedule.check_due(now) {
+                return Ok(schedule.check_at());
+            }
+        }
+
+        let next_due: Option<f64> = PROMOTE_DELAYED_SCRIPT
+            .key(&self.stream_key)
+            .key(&self.delayed_key)
+            .key(&self.payloads_key)
+            .arg(unix_millis(now))
+            .arg(PROMOTE_DELAYED_LIMIT)
+            .arg(&trim_args(None))
+            .invoke(redis)?;
+
+        let next_due = next_due.map(|ms| SystemTime::UNIX_EPOCH + Duration::from_millis(ms as u64));
+        let mut schedule = self.delayed_schedule.lock().unwrap();
+        schedule.promoted(next_due, self.delayed_poll_interval);
+
+        Ok(schedule.check_at())
+    }
+
+    fn read(
+        &mut self,
+        redis: &mut redis::Connection,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let mut opts = redis::streams::StreamReadOptions::default()
+            .group(&self.queue_name, &self.consumer)
+            .count(n);
+
+        if let Some(timeout) = timeout {
+            opts = opts.block(timeout.as_millis() as usize);
+        }
+
+        let mut res: redis::streams::StreamReadReply =
+            redis.xread_options(&[&self.stream_key], &[">"], &opts)?;
+
+        if res.keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        res.keys
+            .swap_remove(0)
+            .ids
+            .into_iter()
+            .map(|i| parse_entry(i, 1, Duration::ZERO, DeliverySource::Read))
+            .collect()
+    }
+
+    /// Read new entries, blocking for up to `timeout`. Blocks are cut short
+    /// at `check_delayed_at` and every poll interval after, to promote delayed
+    /// items before reading again, or when an autoclaim becomes due.
+    fn read_blocking(
+        &mut self,
+        redis: &mut redis::Connection,
+        n: usize,
+        timeout: Option<Duration>,
+        mut check_delayed_at: SystemTime,
+        autoclaim_at: Option<Instant>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let deadline = timeout
+            .filter(|t| !t.is_zero())
+            .map(|t| Instant::now() + t);
+
+        loop {
+            let mut block = match deadline {
+                Some(deadline) => cap_timeout(timeout, deadline.saturating_duration_since(Instant::now())),
+                None => timeout,
+            };
+
+            // Don't block past the point where delayed items need promoting,
+            // whether one becomes due or the poll interval is up, nor past the
+            // point where an autoclaim becomes due.
+            let until = check_delayed_at.duration_since(SystemTime::now()).unwrap_or_default();
+            block = cap_timeout(block, until);
+            if let Some(at) = autoclaim_at {
+                block = cap_timeout(block, at.saturating_duration_since(Instant::now()));
+            }
+
+            let items = self.read(redis, n, block)?;
+
+            let now = Instant::now();
+            if !items.is_empty()
+                || timeout.is_none()
+                || deadline.is_some_and(|deadline| deadline <= now)
+                || autoclaim_at.is_some_and(|at| at <= now)
+            {
+                return Ok(items);
+            }
+
+            check_delayed_at = self.promote_delayed(redis)?;
+        }
+    }
+
+    fn autoclaim(
+        &mut self,
+        redis: &mut redis::Connection,
+        n: usize,
+        next_stream_id: &str,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        // Hand the cursor back unless this autoclaim completes.
+        let guard = StageGuard::new(
+            &self.dequeue_stage,
+            DequeueStage::Autoclaim {
+                next_stream_id: next_stream_id.to_string(),
+            },
+        );
+
+        let (pending, res): (
+            Vec<(String, String, u64, u64)>,
+            redis::streams::StreamAutoClaimReply,
+        ) = AUTOCLAIM_SCRIPT
+            .key(&self.stream_key)
+            .arg(&self.queue_name)
+            .arg(&self.consumer)
+            .arg(
+                self.autoclaim_options
+                    .as_ref()
+                    .map(|o| o.min_idle_time.as_millis() as usize)
+                    .unwrap(),
+            )
+            .arg(next_stream_id)
+            .arg(n)
+            .invoke(redis)?;
+
+        let pending: HashMap<String, (u64, u64)> = pending
+            .into_iter()
+            .map(|(id, _, idle, deliveries)| (id, (idle, deliveries)))
+            .collect();
+
+        guard.complete(if res.next_stream_id == "0-0" {
+            DequeueStage::read(self.autoclaim_options.as_ref())
+        } else {
+            DequeueStage::Autoclaim {
+                next_stream_id: res.next_stream_id,
+            }
+        });
+
+        // Entries deleted while pending can't be delivered again.
+        if !res.deleted_ids.is_empty() {
+            let _: () = redis.xack(&self.stream_key, &self.queue_name, &res.deleted_ids)?;
+        }
+
+        res.claimed
+            .into_iter()
+            .map(|i| {
+                let (idle, deliveries) = pending.get(&i.id).copied().unwrap_or_default();
+                parse_entry(
+                    i,
+                    deliveries + 1,
+                    Duration::from_millis(idle),
+                    DeliverySource::Autoclaim,
+                )
+            })
+            .collect()
+    }
+}
+
+impl<I: Item> SyncBackend<I> for Stream<I> {
+    type Id = String;
+
+    fn enqueue(&mut self, item: &I) -> Result<String, Error> {
+        let item = item.to_stream()?;
+        let id = self.pool.get()?.xadd(&self.stream_key, "*", &item)?;
+
+        Ok(id)
+    }
+
+    fn enqueue_at(&mut self, item: &I, at: SystemTime) -> Result<(), Error> {
+        if at <= SystemTime::now() {
+            return self.enqueue(item).map(|_| ());
+        }
+
+        let invocation = enqueue_delayed(&self.delayed_key, &self.payloads_key, item, at)?;
+        let _: () = invocation.invoke(&mut *self.pool.get()?)?;
+        self.delayed_schedule.lock().unwrap().delayed(at);
+
+        Ok(())
+    }
+
+    fn dequeue(&mut self, n: usize, timeout: Option<Duration>) -> Result<Vec<I>, Error> {
+        let deliveries = self.dequeue_deliveries(n, timeout)?;
+        Ok(deliveries.into_iter().map(|d| d.item).collect())
+    }
+
+    fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        let mut redis = self.pool.get()?;
+        let check_delayed_at = self.promote_delayed(&mut redis)?;
+
+        let step = self.dequeue_stage.lock().unwrap().claim();
+        match step {
+            DequeueStep::Read { autoclaim_at } => {
+                self.read_blocking(&mut redis, n, timeout, check_delayed_at, autoclaim_at)
+            }
+            // Recovery is only configured on the async stream.
+            DequeueStep::Recover(_) => self.read_blocking(&mut redis, n, timeout, check_delayed_at, None),
+            DequeueStep::Autoclaim(next_stream_id) => self.autoclaim(&mut redis, n, &next_stream_id),
+        }
+    }
+
+    fn ack(&mut self, items: &[&I]) -> Result<(), Error> {
+        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let _: () = self
+            .pool
+            .get()?
+            .xack(&self.stream_key, &self.queue_name, &ids)?;
+
+        Ok(())
+    }
+
+    /// Nacked items are re-added to the stream as new entries, carrying their
+    /// delivery count over.
+    fn nack(&mut self, items: &[&I], requeue_delay: Option<Duration>) -> Result<(), Error> {
+        let ids: Vec<&str> = items.iter().filter_map(|i| i.id()).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let at = requeue_delay.map(due_in).transpose()?;
+
+        let _: () = NACK_SCRIPT
+            .key(&self.stream_key)
+            .key(&self.delayed_key)
+            .key(&self.payloads_key)
+            .arg(&self.queue_name)
+            .arg(&self.consumer)
+            .arg(at.map(unix_millis).unwrap_or(0))
+            .arg(&trim_args(None))
+            .arg(&ids)
+            .invoke(&mut *self.pool.get()?)?;
+
+        if let Some(at) = at {
+            self.delayed_schedule.lock().unwrap().delayed(at);
+        }
+
+        Ok(())
+    }
+
+    fn drop_items(&mut self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error> {
+        let mut redis = self.pool.get()?;
+        let mut dropped = vec![];
+        let mut scanned = 0;
+        let mut start = "-".to_string();
+
+        loop {
+            let count = match options.scan_limit {
+                Some(scan_limit) => options.count.min(scan_limit - scanned),
+                None => options.count,
+            };
+            if count == 0 {
+                break;
+            }
+
+            let pending: Vec<(String, String, u64, u64)> =
+                pending_page(&self.stream_key, &self.queue_name, &start, count, options)
+                    .invoke(&mut *redis)?;
+
+            scanned += pending.len() as u64;
+            let exhausted = (pending.len() as u64) < count;
+            if let Some((last, _, _, _)) = pending.last() {
+                start = format!("({last}");
+            }
+
+            let mut drop = droppable(pending, options);
+            if !drop.is_empty() {
+                if options.payloads {
+                    let mut pipe = redis::pipe();
+                    for d in drop.iter() {
+                        pipe.xrange(&self.stream_key, &d.id, &d.id);
+                    }
+
+                    let entries: Vec<redis::streams::StreamRangeReply> = pipe.query(&mut *redis)?;
+                    for (d, entry) in drop.iter_mut().zip(entries) {
+                        d.payload = entry.ids.into_iter().next().map(dropped_payload);
+                    }
+                }
+
+                match &self.dead_letter_stream {
+                    Some(dead_letter_key) => {
+                        let mut invocation = DEAD_LETTER_SCRIPT.key(&self.stream_key);
+                        invocation.key(dead_letter_key).arg(&self.queue_name);
+                        for d in drop.iter() {
+                            invocation.arg(&d.id).arg(d.deliveries).arg(d.idle);
+                        }
+
+                        let _: () = invocation.invoke(&mut *redis)?;
+                    }
+                    None => {
+                        let drop_ids: Vec<&str> = drop.iter().map(|d| d.id.as_str()).collect();
+                        let _: () = redis.xack(&self.stream_key, &self.queue_name, &drop_ids)?;
+                    }
+                }
+
+                dropped.extend(drop);
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(dropped)
+    }
+}
+
+fn parse_entry<I: Item>(
+    entry: redis::streams::StreamId,
+    deliveries: u64,
+    idle: Duration,
+    source: DeliverySource,
+) -> Result<Delivery<I>, Error> {
+    match I::from_stream(&entry) {
+        Ok(item) => Ok(Delivery {
+            item,
+            deliveries: prior_attempts(&entry) + deliveries,
+            idle,
+            enqueued_at: stream_id_time(&entry.id),
+            source,
+        }),
+        Err(e) => Err(Error::ParseError(entry, Box::new(e))),
+    }
+}
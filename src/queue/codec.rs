@@ -0,0 +1,100 @@
+use crate::queue::error::Error;
+
+/// A serde encoding for `CodecItem` payloads.
+pub trait Codec {
+    /// Stored in the entry's `content-type` field and checked on decode.
+    const CONTENT_TYPE: &'static str;
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error>;
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Json;
+
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MessagePack;
+
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cbor;
+
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bincode;
+
+#[cfg(feature = "postcard")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Postcard;
+
+impl Codec for Json {
+    const CONTENT_TYPE: &'static str = "application/json";
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(|e| Error::SerializeError(Box::new(e)))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        serde_json::from_slice(bytes).map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    const CONTENT_TYPE: &'static str = "application/msgpack";
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        rmp_serde::to_vec_named(value).map_err(|e| Error::SerializeError(Box::new(e)))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        rmp_serde::from_slice(bytes).map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    const CONTENT_TYPE: &'static str = "application/cbor";
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        let mut bytes = vec![];
+        ciborium::into_writer(value, &mut bytes)
+            .map_err(|e| Error::SerializeError(Box::new(e)))?;
+
+        Ok(bytes)
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        ciborium::from_reader(bytes).map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    const CONTENT_TYPE: &'static str = "application/x-bincode";
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        bincode::serde::encode_to_vec(value, bincode::config::standard())
+            .map_err(|e| Error::SerializeError(Box::new(e)))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+            .map(|(value, _)| value)
+            .map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {
+    const CONTENT_TYPE: &'static str = "application/x-postcard";
+
+    fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        postcard::to_allocvec(value).map_err(|e| Error::SerializeError(Box::new(e)))
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+        postcard::from_bytes(bytes).map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, SystemTime};
+
+/// A dequeued item along with metadata about its delivery.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery<I> {
+    pub item: I,
+    /// Number of times the item has been delivered, including this delivery.
+    pub deliveries: u64,
+    /// How long the item sat idle since its previous delivery, zero for new items.
+    pub idle: Duration,
+    /// When the item was enqueued, if the backend's ids encode it.
+    pub enqueued_at: Option<SystemTime>,
+    pub source: DeliverySource,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeliverySource {
+    /// A new item, read for the first time.
+    Read,
+    /// A pending item reclaimed after being idle, e.g. via `XAUTOCLAIM`.
+    Autoclaim,
+    /// A pending item of this consumer, redelivered after a restart.
+    Recovered,
+}
+
+impl<I> Delivery<I> {
+    /// A first delivery of a new item, with no enqueue time.
+    pub fn new(item: I) -> Self {
+        Self {
+            item,
+            deliveries: 1,
+            idle: Duration::ZERO,
+            enqueued_at: None,
+            source: DeliverySource::Read,
+        }
+    }
+
+    pub fn map<J>(self, f: impl FnOnce(I) -> J) -> Delivery<J> {
+        Delivery {
+            item: f(self.item),
+            deliveries: self.deliveries,
+            idle: self.idle,
+            enqueued_at: self.enqueued_at,
+            source: self.source,
+        }
+    }
+}
+
+/// Extract the enqueue time from a Redis stream id (`<ms>-<seq>`).
+pub(crate) fn stream_id_time(id: &str) -> Option<SystemTime> {
+    let (ms, _) = id.split_once('-')?;
+    let ms = ms.parse().ok()?;
+
+    Some(SystemTime::UNIX_EPOCH + Duration::from_millis(ms))
+}
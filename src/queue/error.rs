@@ -3,7 +3,14 @@
 pub enum Error {
     R2d2Error(r2d2::Error),
     RedisError(redis::RedisError),
-    ParseError(redis::streams::StreamId)
+    /// An entry that `Item::from_stream` failed to parse, with the error it
+    /// failed with.
+    ParseError(redis::streams::StreamId, Box<Error>),
+    SerializeError(Box<dyn std::error::Error + Send + Sync>),
+    DeserializeError(Box<dyn std::error::Error + Send + Sync>),
+    CapacityError(usize),
+    /// A delay too long to turn into a due time.
+    DelayError(std::time::Duration)
 }
 
 impl From<r2d2::Error> for Error {
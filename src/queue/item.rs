@@ -1,8 +1,10 @@
+use crate::queue::codec::Codec;
+use crate::queue::error::Error;
 
 pub trait Item: Sized {
     fn id(&self) -> Option<&str>;
-    fn from_stream(stream_id: &redis::streams::StreamId) -> Option<Self>;
-    fn to_stream(&self) -> Vec<(&str, String)>;
+    fn from_stream(stream_id: &redis::streams::StreamId) -> Result<Self, Error>;
+    fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, Error>;
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -22,40 +24,165 @@ impl<I: serde::de::DeserializeOwned + serde::Serialize + Sized> Item for JsonIte
         self.id.as_ref().map(|i| i.as_str())
     }
 
-    fn from_stream(stream_id: &redis::streams::StreamId) -> Option<Self> {
-        stream_id
+    fn from_stream(stream_id: &redis::streams::StreamId) -> Result<Self, Error> {
+        let json: String = stream_id
             .get("json")
-            .and_then(|json: String| serde_json::from_str(&json).ok())
-            .and_then(|item| {
-                let instance = Self {
-                    id: Some(stream_id.id.clone()),
-                    item
-                };
+            .ok_or_else(|| Error::DeserializeError("missing `json` field".into()))?;
 
-                Some(instance)
-            })
+        let item = serde_json::from_str(&json)
+            .map_err(|e| Error::DeserializeError(Box::new(e)))?;
+
+        let instance = Self {
+            id: Some(stream_id.id.clone()),
+            item
+        };
+
+        Ok(instance)
+    }
+
+    fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, Error> {
+        let json = serde_json::to_vec(&self.item)
+            .map_err(|e| Error::SerializeError(Box::new(e)))?;
+
+        Ok(vec![("json", json)])
+    }
+}
+
+/// An item stored as a `payload` field encoded with `C`, alongside a
+/// `content-type` field identifying the codec.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodecItem<I, C> {
+    pub id: Option<String>,
+    pub item: I,
+    codec: std::marker::PhantomData<C>
+}
+
+impl<I, C> CodecItem<I, C> {
+    pub fn new(item: I) -> Self {
+        Self { id: None, item, codec: std::marker::PhantomData }
+    }
+}
+
+impl<I: serde::de::DeserializeOwned + serde::Serialize, C: Codec> Item for CodecItem<I, C> {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    fn from_stream(stream_id: &redis::streams::StreamId) -> Result<Self, Error> {
+        let content_type: String = stream_id
+            .get("content-type")
+            .ok_or_else(|| Error::DeserializeError("missing `content-type` field".into()))?;
+
+        if content_type != C::CONTENT_TYPE {
+            let message = format!("unexpected content type `{content_type}`, expected `{}`", C::CONTENT_TYPE);
+            return Err(Error::DeserializeError(message.into()));
+        }
+
+        let payload: Vec<u8> = stream_id
+            .get("payload")
+            .ok_or_else(|| Error::DeserializeError("missing `payload` field".into()))?;
+
+        let instance = Self {
+            id: Some(stream_id.id.clone()),
+            item: C::decode(&payload)?,
+            codec: std::marker::PhantomData
+        };
+
+        Ok(instance)
     }
 
-    fn to_stream(&self) -> Vec<(&str, String)> {
-        let json = serde_json::to_string(&self.item).unwrap();
-        vec![("json", json)]
+    fn to_stream(&self) -> Result<Vec<(&str, Vec<u8>)>, Error> {
+        Ok(vec![
+            ("content-type", C::CONTENT_TYPE.as_bytes().to_vec()),
+            ("payload", C::encode(&self.item)?)
+        ])
+    }
+}
+
+/// Field conversions used by `#[derive(Item)]`.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod derive {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use crate::queue::error::Error;
+
+    pub use redis::streams::StreamId;
+
+    pub fn encode<T: ToString>(value: &T) -> Vec<u8> {
+        value.to_string().into_bytes()
+    }
+
+    pub fn encode_json<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(value).map_err(|e| Error::SerializeError(Box::new(e)))
+    }
+
+    pub fn decode<T>(stream_id: &StreamId, name: &str) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        decode_optional(stream_id, name)?.ok_or_else(|| missing(name))
+    }
+
+    pub fn decode_optional<T>(stream_id: &StreamId, name: &str) -> Result<Option<T>, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = stream_id.get::<String>(name) else {
+            return Ok(None);
+        };
+
+        value
+            .parse()
+            .map(Some)
+            .map_err(|e| Error::DeserializeError(format!("invalid `{name}` field: {e}").into()))
+    }
+
+    pub fn decode_json<T: serde::de::DeserializeOwned>(stream_id: &StreamId, name: &str) -> Result<T, Error> {
+        decode_json_optional(stream_id, name)?.ok_or_else(|| missing(name))
+    }
+
+    pub fn decode_json_optional<T: serde::de::DeserializeOwned>(stream_id: &StreamId, name: &str) -> Result<Option<T>, Error> {
+        let Some(value) = stream_id.get::<Vec<u8>>(name) else {
+            return Ok(None);
+        };
+
+        serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(|e| Error::DeserializeError(Box::new(e)))
+    }
+
+    fn missing(name: &str) -> Error {
+        Error::DeserializeError(format!("missing `{name}` field").into())
     }
 }
 
 #[cfg(test)]
 mod tests {
     mod json_item {
-        use crate::queue::{Item, JsonItem};
+        use crate::queue::{Error, Item, JsonItem};
 
         #[test]
         fn serializes_json_correctly() {
             let item = JsonItem::new("123".to_string());
-            let serialized = item.to_stream();
+            let serialized = item.to_stream().unwrap();
 
-            let expected = vec![("json", "\"123\"".to_string())];
+            let expected = vec![("json", b"\"123\"".to_vec())];
             assert_eq!(serialized, expected);
         }
 
+        #[test]
+        fn serialization_fails_gracefully() {
+            // JSON object keys must be strings
+            let item = JsonItem::new(std::collections::HashMap::from([((1, 2), 3)]));
+            let serialized = item.to_stream();
+
+            assert_eq!(matches!(serialized, Err(Error::SerializeError(_))), true);
+        }
+
         #[test]
         fn deserializes_correctly() {
             let sid = redis::streams::StreamId {
@@ -79,8 +206,92 @@ mod tests {
                 )
             };
 
-            let item : Option<JsonItem<i32>> = JsonItem::from_stream(&sid);
-            assert_eq!(item.is_none(), true);
+            let item : Result<JsonItem<i32>, Error> = JsonItem::from_stream(&sid);
+            assert_eq!(matches!(item, Err(Error::DeserializeError(_))), true);
+        }
+
+        #[test]
+        fn deserialization_fails_on_missing_field() {
+            let sid = redis::streams::StreamId {
+                id: "foo".to_string(),
+                map: std::collections::HashMap::new()
+            };
+
+            let item : Result<JsonItem<i32>, Error> = JsonItem::from_stream(&sid);
+            assert_eq!(matches!(item, Err(Error::DeserializeError(_))), true);
+        }
+    }
+
+    mod codec_item {
+        use crate::queue::codec::{Codec, Json};
+        use crate::queue::{CodecItem, Error, Item};
+
+        #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Payload {
+            name: String,
+            values: Vec<i32>,
+        }
+
+        fn round_trip<C: Codec + std::fmt::Debug + PartialEq>() {
+            let payload = Payload { name: "foo".to_string(), values: vec![1, 2, 3] };
+            let item: CodecItem<Payload, C> = CodecItem::new(payload.clone());
+
+            let sid = redis::streams::StreamId {
+                id: "foo".to_string(),
+                map: item
+                    .to_stream()
+                    .unwrap()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), redis::Value::BulkString(v)))
+                    .collect()
+            };
+
+            let item: CodecItem<Payload, C> = CodecItem::from_stream(&sid).unwrap();
+            assert_eq!(item.id, Some("foo".to_string()));
+            assert_eq!(item.item, payload);
+        }
+
+        #[test]
+        fn round_trips_json() {
+            round_trip::<Json>();
+        }
+
+        #[cfg(feature = "msgpack")]
+        #[test]
+        fn round_trips_msgpack() {
+            round_trip::<crate::queue::codec::MessagePack>();
+        }
+
+        #[cfg(feature = "cbor")]
+        #[test]
+        fn round_trips_cbor() {
+            round_trip::<crate::queue::codec::Cbor>();
+        }
+
+        #[cfg(feature = "bincode")]
+        #[test]
+        fn round_trips_bincode() {
+            round_trip::<crate::queue::codec::Bincode>();
+        }
+
+        #[cfg(feature = "postcard")]
+        #[test]
+        fn round_trips_postcard() {
+            round_trip::<crate::queue::codec::Postcard>();
+        }
+
+        #[test]
+        fn rejects_mismatched_content_type() {
+            let sid = redis::streams::StreamId {
+                id: "foo".to_string(),
+                map: std::collections::HashMap::from([
+                    ("content-type".to_string(), redis::Value::SimpleString("application/cbor".to_string())),
+                    ("payload".to_string(), redis::Value::BulkString(b"1".to_vec()))
+                ])
+            };
+
+            let item : Result<CodecItem<i32, Json>, Error> = CodecItem::from_stream(&sid);
+            assert_eq!(matches!(item, Err(Error::DeserializeError(_))), true);
         }
     }
 }
@@ -1,11 +1,27 @@
 pub mod backend;
+pub mod blocking;
+pub mod codec;
+pub mod delivery;
 pub mod error;
 pub mod item;
 pub mod queue;
+pub mod retry;
+pub mod shared;
+pub mod sink;
+pub mod worker;
 
-pub use backend::{Backend, DroppedItem, DropOptions};
+pub use backend::{Backend, DroppedItem, DroppedPayload, DropOptions};
 pub use backend::combine;
+pub use backend::memory;
 pub use backend::stream;
+pub use delivery::{Delivery, DeliverySource};
 pub use error::Error;
-pub use item::{Item, JsonItem};
+pub use codec::Codec;
+pub use item::{CodecItem, Item, JsonItem};
+#[cfg(feature = "derive")]
+pub use rdq_derive::Item;
 pub use queue::Queue;
+pub use retry::{Backoff, RetryDecision, RetryPolicy};
+pub use shared::{SharedBackend, SharedQueue};
+pub use sink::QueueSink;
+pub use worker::{Handler, Outcome, Worker, WorkerOptions};
@@ -1,27 +1,80 @@
-use crate::queue::backend::{Backend, DropOptions, DroppedItem};
+use std::collections::VecDeque;
+
+use crate::queue::backend::{Backend, DropOptions, DroppedItem, due_in};
+use crate::queue::delivery::Delivery;
 use crate::queue::error::Error;
+use crate::queue::retry::{RetryDecision, RetryPolicy};
+use crate::queue::sink::QueueSink;
 
 #[derive(Clone)]
 pub struct Queue<I, B: Backend<I>> {
     i: std::marker::PhantomData<I>,
-    backend: B
+    backend: B,
+    retry_policy: Option<RetryPolicy>
 }
 
 impl<I, B: Backend<I>> Queue<I, B> {
     pub fn new(backend: B) -> Self {
         Self {
             i: std::marker::PhantomData::default(),
-            backend
+            backend,
+            retry_policy: None
         }
     }
 
+    /// Decide how failed items are retried in `retry`, and by workers.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Enqueue an item, returning its id.
     pub async fn enqueue(
         &mut self,
         item: &I
-    ) -> Result<(), Error> {
+    ) -> Result<B::Id, Error> {
         self.backend.enqueue(item).await
     }
 
+    /// Enqueue all items in one go, returning their ids in order.
+    pub async fn enqueue_batch(
+        &mut self,
+        items: &[I]
+    ) -> Result<Vec<B::Id>, Error> {
+        let items: Vec<&I> = items.iter().collect();
+        self.backend.enqueue_batch(&items).await
+    }
+
+    /// Enqueue an item that only becomes available for dequeue at `at`.
+    pub async fn enqueue_at(
+        &mut self,
+        item: &I,
+        at: std::time::SystemTime
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, at).await
+    }
+
+    /// Enqueue an item that only becomes available for dequeue after `delay`.
+    pub async fn enqueue_in(
+        &mut self,
+        item: &I,
+        delay: std::time::Duration
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, due_in(delay)?).await
+    }
+
     pub async fn dequeue(
         &mut self,
         n: usize,
@@ -30,6 +83,15 @@ impl<I, B: Backend<I>> Queue<I, B> {
         self.backend.dequeue(n, timeout).await
     }
 
+    /// Dequeue items along with their delivery metadata.
+    pub async fn dequeue_deliveries(
+        &mut self,
+        n: usize,
+        timeout: Option<std::time::Duration>
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        self.backend.dequeue_deliveries(n, timeout).await
+    }
+
     pub async fn ack(
         &mut self,
         items: &Vec<&I>
@@ -37,10 +99,222 @@ impl<I, B: Backend<I>> Queue<I, B> {
         self.backend.ack(items).await
     }
 
+    /// Return items to the queue, either immediately or after `requeue_delay`.
+    pub async fn nack(
+        &mut self,
+        items: &[&I],
+        requeue_delay: Option<std::time::Duration>
+    ) -> Result<(), Error> {
+        self.backend.nack(items, requeue_delay).await
+    }
+
+    /// Move items to the backend's dead letter, acking them.
+    pub async fn dead_letter(
+        &mut self,
+        items: &[&I]
+    ) -> Result<(), Error> {
+        self.backend.dead_letter(items).await
+    }
+
+    /// Handle a failed delivery according to the retry policy, optionally
+    /// with the error it failed with. The item is either nacked with the
+    /// policy's back-off, or dead-lettered once its attempts are exhausted.
+    /// Without a retry policy the item is nacked for immediate redelivery.
+    pub async fn retry(
+        &mut self,
+        delivery: &Delivery<I>,
+        error: Option<&(dyn std::error::Error + 'static)>
+    ) -> Result<RetryDecision, Error> {
+        self.retry_after(delivery, error, None).await
+    }
+
+    /// Like `retry`, but nacking with `delay` in place of the policy's
+    /// back-off when one is given. The policy still dead-letters the item once
+    /// its attempts are exhausted or its error isn't retryable.
+    pub async fn retry_after(
+        &mut self,
+        delivery: &Delivery<I>,
+        error: Option<&(dyn std::error::Error + 'static)>,
+        delay: Option<std::time::Duration>
+    ) -> Result<RetryDecision, Error> {
+        let decision = match &self.retry_policy {
+            Some(policy) => match policy.decide(delivery.deliveries, error) {
+                RetryDecision::Retry(backoff) => RetryDecision::Retry(delay.unwrap_or(backoff)),
+                RetryDecision::DeadLetter => RetryDecision::DeadLetter,
+            },
+            None => RetryDecision::Retry(delay.unwrap_or_default()),
+        };
+
+        match decision {
+            RetryDecision::Retry(delay) if delay.is_zero() => self.nack(&[&delivery.item], None).await?,
+            RetryDecision::Retry(delay) => self.nack(&[&delivery.item], Some(delay)).await?,
+            RetryDecision::DeadLetter => self.dead_letter(&[&delivery.item]).await?,
+        }
+
+        Ok(decision)
+    }
+
     pub async fn drop_items(
         &mut self,
         options: &DropOptions
-    ) -> Result<Vec<DroppedItem>, Error> {
+    ) -> Result<Vec<DroppedItem<I>>, Error> {
         self.backend.drop_items(options).await
     }
+
+    /// Consume the queue as a stream of items, dequeuing up to `batch_size`
+    /// at a time. The next batch is only dequeued once the previous one has
+    /// been consumed, so a slow consumer doesn't pull more than a batch ahead.
+    ///
+    /// With a `timeout` each dequeue blocks as in `dequeue`, and the stream
+    /// runs indefinitely. Without one the stream ends once the queue is empty.
+    /// Either way it ends after yielding an error. Items still need acking,
+    /// e.g. through a clone of the queue taken beforehand.
+    pub fn into_stream(
+        self,
+        batch_size: usize,
+        timeout: Option<std::time::Duration>
+    ) -> impl futures::Stream<Item = Result<I, Error>> {
+        self.into_stream_until(batch_size, timeout, std::future::pending())
+    }
+
+    /// Like `into_stream`, but ends once `shutdown` resolves. Already dequeued
+    /// items are still yielded, while a dequeue blocking when shutdown resolves
+    /// is abandoned, so the stream ends even with an unbounded `timeout`.
+    /// Anything that dequeue had already claimed stays pending on the backend,
+    /// to be redelivered like any other unacked item.
+    pub fn into_stream_until(
+        self,
+        batch_size: usize,
+        timeout: Option<std::time::Duration>,
+        shutdown: impl Future<Output = ()>
+    ) -> impl futures::Stream<Item = Result<I, Error>> {
+        let state = (self, VecDeque::new(), Box::pin(shutdown), false);
+
+        futures::stream::unfold(state, move |(mut queue, mut buffered, mut shutdown, done)| async move {
+            loop {
+                if let Some(item) = buffered.pop_front() {
+                    return Some((Ok(item), (queue, buffered, shutdown, done)));
+                }
+
+                if done {
+                    return None;
+                }
+
+                let res = tokio::select! {
+                    biased;
+                    _ = &mut shutdown => return None,
+                    res = queue.dequeue(batch_size, timeout) => res,
+                };
+
+                match res {
+                    Ok(items) if items.is_empty() && timeout.is_none() => return None,
+                    Ok(items) => buffered.extend(items),
+                    Err(e) => return Some((Err(e), (queue, buffered, shutdown, true))),
+                }
+            }
+        })
+    }
+
+    /// Turn the queue into a `futures::Sink` that enqueues items in batches
+    /// of up to `batch_size`.
+    pub fn into_sink(self, batch_size: usize) -> QueueSink<I, B>
+    where
+        I: Send + Sync + 'static,
+        B: Send + 'static,
+    {
+        QueueSink::new(self, batch_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::memory::MemoryBuilder;
+    use crate::queue::{Error, JsonItem, Queue};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn streams_items_until_empty() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+        for i in 1..=5 {
+            queue.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let items: Vec<i32> = queue
+            .into_stream(2, None)
+            .map(|i| i.unwrap().item)
+            .collect()
+            .await;
+        assert_eq!(items, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn dequeues_lazily() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+        let mut other = queue.clone();
+        for i in 1..=4 {
+            queue.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let mut stream = Box::pin(queue.into_stream(2, None));
+        assert_eq!(stream.next().await.unwrap().unwrap().item, 1);
+
+        // Only the first batch has been dequeued
+        let remaining: Vec<i32> = other
+            .dequeue(10, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(remaining, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn ends_on_shutdown() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let mut stream = Box::pin(queue.into_stream_until(10, Some(Duration::from_millis(10)), async {
+            let _ = rx.await;
+        }));
+
+        assert_eq!(stream.next().await.unwrap().unwrap().item, 1);
+
+        tx.send(()).unwrap();
+        assert_eq!(stream.next().await.is_none(), true);
+    }
+
+    #[tokio::test]
+    async fn ends_on_shutdown_while_blocked() {
+        let queue: Queue<JsonItem<i32>, _> = Queue::new(MemoryBuilder::new().build());
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let mut stream = Box::pin(queue.into_stream_until(10, Some(Duration::ZERO), async {
+            let _ = rx.await;
+        }));
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            tx.send(()).unwrap();
+        });
+
+        // The dequeue blocks indefinitely on the empty queue, until shutdown
+        let next = tokio::time::timeout(Duration::from_secs(1), stream.next()).await;
+        assert_eq!(next.unwrap().is_none(), true);
+    }
+
+    #[tokio::test]
+    async fn rejects_out_of_range_delays() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+
+        let res = queue.enqueue_in(&JsonItem::new(1), Duration::MAX).await;
+        assert_eq!(matches!(res, Err(Error::DelayError(_))), true);
+
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+        let delivery = queue.dequeue_deliveries(1, None).await.unwrap().remove(0);
+        let res = queue.retry_after(&delivery, None, Some(Duration::MAX)).await;
+        assert_eq!(matches!(res, Err(Error::DelayError(_))), true);
+    }
 }
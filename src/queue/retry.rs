@@ -0,0 +1,197 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The default cap on the delay between retries.
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Decides whether a failed item is retried, and after how long, based on how
+/// many times it has been delivered.
+///
+/// Delivery counts come from the backend (`Delivery::deliveries`), and are
+/// carried over when items are nacked, so attempts survive redeliveries.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u64,
+    backoff: Backoff,
+    max_delay: Duration,
+    jitter: f64,
+    classify: Option<ErrorClassifier>,
+}
+
+/// Returns whether an error is worth retrying.
+type ErrorClassifier = Arc<dyn Fn(&(dyn std::error::Error + 'static)) -> bool + Send + Sync>;
+
+#[derive(Clone, Debug)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// `initial`, then growing by `step` with each retry.
+    Linear { initial: Duration, step: Duration },
+    /// `initial`, then multiplied by `factor` with each retry.
+    Exponential { initial: Duration, factor: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetryDecision {
+    /// Nack the item, requeueing it after the delay.
+    Retry(Duration),
+    /// Give up on the item and move it to the dead letter.
+    DeadLetter,
+}
+
+impl RetryPolicy {
+    /// Retry items until they have been delivered `max_attempts` times.
+    pub fn new(max_attempts: u64, backoff: Backoff) -> Self {
+        Self {
+            max_attempts,
+            backoff,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: 0.0,
+            classify: None,
+        }
+    }
+
+    /// Cap the delay between retries, `DEFAULT_MAX_DELAY` by default. Back-offs
+    /// growing past it, however far, are clamped to it.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Shorten each delay by a random fraction of up to `jitter` (between 0
+    /// and 1), so that items failing together aren't all retried at once.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Only retry errors for which `retryable` returns true, dead-lettering
+    /// items on any other error straight away.
+    pub fn classify(
+        mut self,
+        retryable: impl Fn(&(dyn std::error::Error + 'static)) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.classify = Some(Arc::new(retryable));
+        self
+    }
+
+    /// Decide what to do with an item that failed on its `deliveries`th
+    /// delivery, optionally with the error it failed with.
+    pub fn decide(
+        &self,
+        deliveries: u64,
+        error: Option<&(dyn std::error::Error + 'static)>,
+    ) -> RetryDecision {
+        let retryable = match (&self.classify, error) {
+            (Some(classify), Some(error)) => classify(error),
+            _ => true,
+        };
+
+        if !retryable || deliveries >= self.max_attempts {
+            return RetryDecision::DeadLetter;
+        }
+
+        let delay = self.delay(deliveries);
+        let jitter = self.jitter * rand::random::<f64>();
+
+        RetryDecision::Retry(delay.mul_f64(1.0 - jitter))
+    }
+
+    /// The delay before retrying an item that failed on its `deliveries`th
+    /// delivery, before jitter.
+    pub fn delay(&self, deliveries: u64) -> Duration {
+        let retries = deliveries.saturating_sub(1);
+
+        let delay = match &self.backoff {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Linear { initial, step } => {
+                initial.saturating_add(step.saturating_mul(retries.min(u32::MAX as u64) as u32))
+            }
+            Backoff::Exponential { initial, factor } => {
+                let factor = factor.powi(retries.min(i32::MAX as u64) as i32);
+                Duration::try_from_secs_f64(initial.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+            }
+        };
+
+        delay.min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::retry::{Backoff, DEFAULT_MAX_DELAY, RetryDecision, RetryPolicy};
+    use std::time::Duration;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn backoff_delays() {
+        let fixed = RetryPolicy::new(5, Backoff::Fixed(ms(100)));
+        assert_eq!((1..=3).map(|d| fixed.delay(d)).collect::<Vec<_>>(), vec![ms(100); 3]);
+
+        let linear = RetryPolicy::new(5, Backoff::Linear { initial: ms(100), step: ms(50) });
+        assert_eq!(
+            (1..=3).map(|d| linear.delay(d)).collect::<Vec<_>>(),
+            vec![ms(100), ms(150), ms(200)]
+        );
+
+        let exponential = RetryPolicy::new(5, Backoff::Exponential { initial: ms(100), factor: 2.0 })
+            .max_delay(ms(300));
+        assert_eq!(
+            (1..=4).map(|d| exponential.delay(d)).collect::<Vec<_>>(),
+            vec![ms(100), ms(200), ms(300), ms(300)]
+        );
+    }
+
+    #[test]
+    fn dead_letters_after_max_attempts() {
+        let policy = RetryPolicy::new(3, Backoff::Fixed(ms(100)));
+
+        assert_eq!(policy.decide(1, None), RetryDecision::Retry(ms(100)));
+        assert_eq!(policy.decide(2, None), RetryDecision::Retry(ms(100)));
+        assert_eq!(policy.decide(3, None), RetryDecision::DeadLetter);
+    }
+
+    #[test]
+    fn jitter_shortens_delays() {
+        let policy = RetryPolicy::new(3, Backoff::Fixed(ms(100))).jitter(0.5);
+
+        for _ in 0..100 {
+            let RetryDecision::Retry(delay) = policy.decide(1, None) else {
+                panic!("expected a retry");
+            };
+            assert_eq!(delay >= ms(50) && delay <= ms(100), true);
+        }
+    }
+
+    #[test]
+    fn clamps_saturated_delays() {
+        let exponential = RetryPolicy::new(100, Backoff::Exponential { initial: ms(1000), factor: 2.0 })
+            .jitter(0.5);
+        let RetryDecision::Retry(delay) = exponential.decide(70, None) else {
+            panic!("expected a retry");
+        };
+        assert_eq!(delay <= DEFAULT_MAX_DELAY, true);
+
+        let linear = RetryPolicy::new(u64::MAX, Backoff::Linear { initial: ms(1000), step: Duration::MAX });
+        assert_eq!(linear.delay(u64::MAX - 1), DEFAULT_MAX_DELAY);
+
+        let fixed = RetryPolicy::new(3, Backoff::Fixed(Duration::MAX)).jitter(0.5);
+        assert_eq!(matches!(fixed.decide(1, None), RetryDecision::Retry(_)), true);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let policy = RetryPolicy::new(3, Backoff::Fixed(ms(100)))
+            .classify(|e| e.downcast_ref::<std::io::Error>().is_some());
+
+        let transient = std::io::Error::other("timed out");
+        let permanent = std::fmt::Error;
+
+        assert_eq!(policy.decide(1, Some(&transient)), RetryDecision::Retry(ms(100)));
+        assert_eq!(policy.decide(1, Some(&permanent)), RetryDecision::DeadLetter);
+        assert_eq!(policy.decide(1, None), RetryDecision::Retry(ms(100)));
+    }
+}
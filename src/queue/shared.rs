@@ -0,0 +1,165 @@
+//! Backends and queue handles usable through a shared reference, so one
+//! handle can be put in an `Arc` and used from many tasks at once.
+
+use crate::queue::backend::{DropOptions, DroppedItem, due_in};
+use crate::queue::delivery::Delivery;
+use crate::queue::error::Error;
+
+#[async_trait::async_trait]
+pub trait SharedBackend<I>: Send + Sync {
+    /// Identifies an enqueued item, e.g. its stream id.
+    type Id;
+
+    async fn enqueue(&self, item: &I) -> Result<Self::Id, Error>;
+    async fn enqueue_at(&self, item: &I, at: std::time::SystemTime) -> Result<(), Error>;
+    async fn enqueue_batch(&self, items: &[&I]) -> Result<Vec<Self::Id>, Error>;
+    async fn dequeue(&self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<I>, Error>;
+    async fn dequeue_deliveries(&self, n: usize, timeout: Option<std::time::Duration>) -> Result<Vec<Delivery<I>>, Error>;
+    async fn ack(&self, items: &[&I]) -> Result<(), Error>;
+    async fn nack(&self, items: &[&I], requeue_delay: Option<std::time::Duration>) -> Result<(), Error>;
+    async fn dead_letter(&self, items: &[&I]) -> Result<(), Error>;
+    async fn drop_items(&self, options: &DropOptions) -> Result<Vec<DroppedItem<I>>, Error>;
+}
+
+#[derive(Clone)]
+pub struct SharedQueue<I, B: SharedBackend<I>> {
+    i: std::marker::PhantomData<I>,
+    backend: B
+}
+
+impl<I, B: SharedBackend<I>> SharedQueue<I, B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            i: std::marker::PhantomData,
+            backend
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Enqueue an item, returning its id.
+    pub async fn enqueue(
+        &self,
+        item: &I
+    ) -> Result<B::Id, Error> {
+        self.backend.enqueue(item).await
+    }
+
+    /// Enqueue all items in one go, returning their ids in order.
+    pub async fn enqueue_batch(
+        &self,
+        items: &[I]
+    ) -> Result<Vec<B::Id>, Error> {
+        let items: Vec<&I> = items.iter().collect();
+        self.backend.enqueue_batch(&items).await
+    }
+
+    /// Enqueue an item that only becomes available for dequeue at `at`.
+    pub async fn enqueue_at(
+        &self,
+        item: &I,
+        at: std::time::SystemTime
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, at).await
+    }
+
+    /// Enqueue an item that only becomes available for dequeue after `delay`.
+    pub async fn enqueue_in(
+        &self,
+        item: &I,
+        delay: std::time::Duration
+    ) -> Result<(), Error> {
+        self.backend.enqueue_at(item, due_in(delay)?).await
+    }
+
+    pub async fn dequeue(
+        &self,
+        n: usize,
+        timeout: Option<std::time::Duration>
+    ) -> Result<Vec<I>, Error> {
+        self.backend.dequeue(n, timeout).await
+    }
+
+    /// Dequeue items along with their delivery metadata.
+    pub async fn dequeue_deliveries(
+        &self,
+        n: usize,
+        timeout: Option<std::time::Duration>
+    ) -> Result<Vec<Delivery<I>>, Error> {
+        self.backend.dequeue_deliveries(n, timeout).await
+    }
+
+    pub async fn ack(
+        &self,
+        items: &[&I]
+    ) -> Result<(), Error> {
+        self.backend.ack(items).await
+    }
+
+    /// Return items to the queue, either immediately or after `requeue_delay`.
+    pub async fn nack(
+        &self,
+        items: &[&I],
+        requeue_delay: Option<std::time::Duration>
+    ) -> Result<(), Error> {
+        self.backend.nack(items, requeue_delay).await
+    }
+
+    /// Move items to the backend's dead letter, acking them.
+    pub async fn dead_letter(
+        &self,
+        items: &[&I]
+    ) -> Result<(), Error> {
+        self.backend.dead_letter(items).await
+    }
+
+    pub async fn drop_items(
+        &self,
+        options: &DropOptions
+    ) -> Result<Vec<DroppedItem<I>>, Error> {
+        self.backend.drop_items(options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::memory::MemoryBuilder;
+    use crate::queue::{JsonItem, SharedQueue};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn dequeues_across_tasks() {
+        let queue = Arc::new(SharedQueue::new(MemoryBuilder::new().build::<JsonItem<i32>>()));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..4 {
+            let queue = queue.clone();
+            tasks.spawn(async move {
+                let mut handled = vec![];
+                loop {
+                    let Some(item) = queue.dequeue(1, Some(Duration::from_millis(50))).await.unwrap().pop() else {
+                        break;
+                    };
+
+                    queue.ack(&[&item]).await.unwrap();
+                    handled.push(item.item);
+                }
+
+                handled
+            });
+        }
+
+        for i in 1..=20 {
+            queue.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let mut handled: Vec<i32> = tasks.join_all().await.into_iter().flatten().collect();
+        handled.sort();
+
+        // Every item is handled exactly once
+        assert_eq!(handled, (1..=20).collect::<Vec<_>>());
+    }
+}
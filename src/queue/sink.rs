@@ -0,0 +1,151 @@
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures::future::BoxFuture;
+
+use crate::queue::backend::Backend;
+use crate::queue::error::Error;
+use crate::queue::queue::Queue;
+
+/// An in-progress flush, handing the queue back once done.
+type Flush<I, B> = BoxFuture<'static, (Queue<I, B>, Result<(), Error>)>;
+
+/// A `futures::Sink` that buffers items and enqueues them in batches with
+/// `Backend::enqueue_batch`.
+///
+/// Items are flushed once `batch_size` are buffered, and on `poll_flush` or
+/// `poll_close`. A failed flush returns the error and discards that batch,
+/// which may have been partially enqueued.
+pub struct QueueSink<I, B: Backend<I>> {
+    queue: Option<Queue<I, B>>,
+    flushing: Option<Flush<I, B>>,
+    buffer: Vec<I>,
+    batch_size: usize,
+}
+
+// The queue is only ever accessed by value or through `&mut`, never pinned.
+impl<I, B: Backend<I>> Unpin for QueueSink<I, B> {}
+
+impl<I, B> QueueSink<I, B>
+where
+    I: Send + Sync + 'static,
+    B: Backend<I> + Send + 'static,
+{
+    pub fn new(queue: Queue<I, B>, batch_size: usize) -> Self {
+        Self {
+            queue: Some(queue),
+            flushing: None,
+            buffer: Vec::with_capacity(batch_size),
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    /// Take back the queue, discarding any unflushed items. Returns `None`
+    /// while a flush is in progress.
+    pub fn into_inner(self) -> Option<Queue<I, B>> {
+        self.queue
+    }
+}
+
+impl<I, B> futures::Sink<I> for QueueSink<I, B>
+where
+    I: Send + Sync + 'static,
+    B: Backend<I> + Send + 'static,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if self.buffer.len() >= self.batch_size {
+            return self.poll_flush(cx);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Error> {
+        self.get_mut().buffer.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(flushing) = this.flushing.as_mut() {
+                let (queue, res) = ready!(flushing.as_mut().poll(cx));
+                this.flushing = None;
+                this.queue = Some(queue);
+                res?;
+            }
+
+            if this.buffer.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut queue = this.queue.take().expect("queue is held while not flushing");
+            let items = std::mem::take(&mut this.buffer);
+
+            this.flushing = Some(Box::pin(async move {
+                let items: Vec<&I> = items.iter().collect();
+                let res = queue.backend_mut().enqueue_batch(&items).await.map(|_| ());
+                (queue, res)
+            }));
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::memory::MemoryBuilder;
+    use crate::queue::sink::QueueSink;
+    use crate::queue::{Error, JsonItem, Queue};
+    use futures::{SinkExt, StreamExt};
+
+    #[tokio::test]
+    async fn forwards_items() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+        let sink = QueueSink::new(queue.clone(), 2);
+
+        futures::stream::iter((1..=5).map(|i| Ok(JsonItem::new(i))))
+            .forward(sink)
+            .await
+            .unwrap();
+
+        let dequeued: Vec<i32> = queue
+            .dequeue(10, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn buffers_until_flushed() {
+        let mut queue = Queue::new(MemoryBuilder::new().build());
+        let mut sink = QueueSink::new(queue.clone(), 10);
+
+        sink.feed(JsonItem::new(1)).await.unwrap();
+        assert_eq!(queue.dequeue(10, None).await.unwrap().is_empty(), true);
+
+        sink.flush().await.unwrap();
+        assert_eq!(queue.dequeue(10, None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn returns_flush_errors() {
+        let queue = Queue::new(MemoryBuilder::new().capacity(1).build());
+        let mut sink = QueueSink::new(queue, 10);
+
+        sink.feed(JsonItem::new(1)).await.unwrap();
+        sink.feed(JsonItem::new(2)).await.unwrap();
+
+        let res = sink.flush().await;
+        assert_eq!(matches!(res, Err(Error::CapacityError(1))), true);
+    }
+}
@@ -0,0 +1,411 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::queue::backend::Backend;
+use crate::queue::delivery::Delivery;
+use crate::queue::error::Error;
+use crate::queue::queue::Queue;
+
+#[async_trait::async_trait]
+pub trait Handler<I> {
+    async fn handle(&self, item: &I) -> Outcome;
+}
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// The item was processed and is acked.
+    Success,
+    /// The item failed transiently and is handed to `Queue::retry_after`, so
+    /// the queue's retry policy (if any) classifies `error` and dead-letters
+    /// the item once its attempts are exhausted. Otherwise it's nacked after
+    /// `delay`, or the policy's back-off without one.
+    Retry {
+        delay: Option<Duration>,
+        error: Option<Arc<dyn std::error::Error + Send + Sync>>,
+    },
+    /// The item failed permanently and is moved to the backend's dead letter
+    /// (acked, without one) rather than retried.
+    Fail,
+}
+
+#[derive(Clone)]
+pub struct WorkerOptions {
+    /// Maximum number of items handled at once.
+    pub concurrency: usize,
+    /// Maximum number of items dequeued per call.
+    pub batch_size: usize,
+    /// Items whose handler exceeds this are retried as if it had returned
+    /// `Outcome::Retry` with no delay or error.
+    pub item_timeout: Option<Duration>,
+    /// Blocking timeout for each dequeue, bounding how long shutdown waits
+    /// for an in-progress dequeue.
+    pub poll_timeout: Duration,
+}
+
+pub struct Worker<I, B: Backend<I>, H: Handler<I>> {
+    queue: Queue<I, B>,
+    handler: Arc<H>,
+    options: WorkerOptions,
+}
+
+impl Default for WorkerOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 1,
+            batch_size: 10,
+            item_timeout: None,
+            poll_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl<I, B, H> Worker<I, B, H>
+where
+    I: Send + Sync + 'static,
+    B: Backend<I> + Send,
+    H: Handler<I> + Send + Sync + 'static,
+{
+    pub fn new(queue: Queue<I, B>, handler: H, options: WorkerOptions) -> Self {
+        Self {
+            queue,
+            handler: Arc::new(handler),
+            options,
+        }
+    }
+
+    /// Dequeue and handle items until `shutdown` resolves or the queue
+    /// returns an error. Either way the worker stops dequeuing and waits for
+    /// in-flight items to finish and be acked/nacked before returning.
+    ///
+    /// Items whose handler panics are neither acked nor nacked, and are left
+    /// pending for redelivery.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) -> Result<(), Error> {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut stopping = false;
+        let mut result = Ok(());
+
+        loop {
+            let mut done = vec![];
+            while let Some(res) = tasks.try_join_next() {
+                done.extend(res.ok());
+            }
+
+            if let Err(e) = self.settle(done).await {
+                stopping = true;
+                result = result.and(Err(e));
+            }
+
+            if !stopping {
+                tokio::select! {
+                    biased;
+                    _ = &mut shutdown => stopping = true,
+                    _ = std::future::ready(()) => {}
+                }
+            }
+
+            let available = self.options.concurrency.saturating_sub(tasks.len());
+
+            if stopping || available == 0 {
+                if tasks.is_empty() {
+                    break;
+                }
+
+                tokio::select! {
+                    Some(res) = tasks.join_next() => {
+                        if let Err(e) = self.settle(res.ok().into_iter().collect()).await {
+                            stopping = true;
+                            result = result.and(Err(e));
+                        }
+                    }
+                    _ = &mut shutdown, if !stopping => stopping = true,
+                }
+
+                continue;
+            }
+
+            let n = self.options.batch_size.min(available);
+            match self.queue.dequeue_deliveries(n, Some(self.options.poll_timeout)).await {
+                Ok(deliveries) => {
+                    for delivery in deliveries {
+                        let handler = self.handler.clone();
+                        let item_timeout = self.options.item_timeout;
+
+                        tasks.spawn(async move {
+                            let outcome = match item_timeout {
+                                Some(t) => tokio::time::timeout(t, handler.handle(&delivery.item))
+                                    .await
+                                    .unwrap_or(Outcome::Retry { delay: None, error: None }),
+                                None => handler.handle(&delivery.item).await,
+                            };
+
+                            (delivery, outcome)
+                        });
+                    }
+                }
+                Err(e) => {
+                    stopping = true;
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+
+    async fn settle(&mut self, done: Vec<(Delivery<I>, Outcome)>) -> Result<(), Error> {
+        if done.is_empty() {
+            return Ok(());
+        }
+
+        let ack = done
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Success))
+            .map(|(delivery, _)| &delivery.item)
+            .collect();
+        self.queue.ack(&ack).await?;
+
+        let failed: Vec<&I> = done
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Fail))
+            .map(|(delivery, _)| &delivery.item)
+            .collect();
+        if !failed.is_empty() {
+            self.queue.dead_letter(&failed).await?;
+        }
+
+        for (delivery, outcome) in done.iter() {
+            if let Outcome::Retry { delay, error } = outcome {
+                let error = error.as_deref().map(|e| e as &(dyn std::error::Error + 'static));
+                self.queue.retry_after(delivery, error, *delay).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::backend::memory::{Memory, MemoryBuilder};
+    use crate::queue::worker::{Handler, Outcome, Worker, WorkerOptions};
+    use crate::queue::{Backend, Backoff, DropOptions, JsonItem, Queue, RetryPolicy};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Clone)]
+    struct TestHandler {
+        handled: Arc<Mutex<Vec<i32>>>,
+        retry: Arc<Mutex<Vec<i32>>>,
+        delay: Duration,
+        retry_delay: Option<Duration>,
+        retry_error: Option<Arc<dyn std::error::Error + Send + Sync>>,
+    }
+
+    impl TestHandler {
+        fn new(retry: Vec<i32>, delay: Duration) -> Self {
+            Self {
+                handled: Arc::new(Mutex::new(vec![])),
+                retry: Arc::new(Mutex::new(retry)),
+                delay,
+                retry_delay: None,
+                retry_error: None,
+            }
+        }
+
+        fn get_handled(&self) -> Vec<i32> {
+            let mut handled = self.handled.lock().unwrap().clone();
+            handled.sort();
+            handled
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Handler<JsonItem<i32>> for TestHandler {
+        async fn handle(&self, item: &JsonItem<i32>) -> Outcome {
+            tokio::time::sleep(self.delay).await;
+            self.handled.lock().unwrap().push(item.item);
+
+            let mut retry = self.retry.lock().unwrap();
+            match retry.iter().position(|i| *i == item.item) {
+                Some(pos) => {
+                    retry.remove(pos);
+                    Outcome::Retry {
+                        delay: self.retry_delay,
+                        error: self.retry_error.clone(),
+                    }
+                }
+                None => Outcome::Success,
+            }
+        }
+    }
+
+    async fn pending(m: &mut Memory<JsonItem<i32>>) -> usize {
+        let drop_options = DropOptions {
+            min_idle_time: Duration::from_millis(0),
+            max_deliveries: 0,
+            count: 100,
+            ..Default::default()
+        };
+
+        m.drop_items(&drop_options).await.unwrap().len()
+    }
+
+    fn options() -> WorkerOptions {
+        WorkerOptions {
+            concurrency: 2,
+            batch_size: 2,
+            item_timeout: None,
+            poll_timeout: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn handles_and_acks_items() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=5 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let handler = TestHandler::new(vec![], Duration::from_millis(1));
+        let worker = Worker::new(Queue::new(m.clone()), handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        assert_eq!(handler.get_handled(), vec![1, 2, 3, 4, 5]);
+
+        // Everything was acked, so nothing is pending
+        assert_eq!(pending(&mut m).await, 0);
+    }
+
+    #[tokio::test]
+    async fn retries_items() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=3 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        let handler = TestHandler::new(vec![2], Duration::from_millis(1));
+        let worker = Worker::new(Queue::new(m.clone()), handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        assert_eq!(handler.get_handled(), vec![1, 2, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn retries_timed_out_items() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let handler = TestHandler::new(vec![], Duration::from_millis(50));
+        let options = WorkerOptions {
+            item_timeout: Some(Duration::from_millis(10)),
+            ..options()
+        };
+        let worker = Worker::new(Queue::new(m.clone()), handler.clone(), options);
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(30)))
+            .await
+            .unwrap();
+
+        // Handler never completed, item was requeued
+        assert_eq!(handler.get_handled().is_empty(), true);
+        let dequeued = m.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn drains_in_flight_items_on_shutdown() {
+        let mut m = MemoryBuilder::new().build();
+        for i in 1..=2 {
+            m.enqueue(&JsonItem::new(i)).await.unwrap();
+        }
+
+        // Shutdown fires while both items are still being handled
+        let handler = TestHandler::new(vec![], Duration::from_millis(100));
+        let worker = Worker::new(Queue::new(m.clone()), handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(20)))
+            .await
+            .unwrap();
+
+        assert_eq!(handler.get_handled(), vec![1, 2]);
+        assert_eq!(pending(&mut m).await, 0);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_items_after_retry_policy() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        // Item fails on every delivery
+        let handler = TestHandler::new(vec![1; 10], Duration::from_millis(1));
+        let policy = RetryPolicy::new(3, Backoff::Exponential {
+            initial: Duration::from_millis(5),
+            factor: 2.0,
+        });
+        let queue = Queue::new(m.clone()).with_retry_policy(policy);
+        let worker = Worker::new(queue, handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        // Retried with back-off until attempts ran out, then dead-lettered
+        assert_eq!(handler.get_handled(), vec![1, 1, 1]);
+        assert_eq!(pending(&mut m).await, 0);
+        assert_eq!(m.dequeue(1, None).await.unwrap().is_empty(), true);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_items_retried_with_a_delay() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        // The handler's delay replaces the back-off, but not the attempt limit
+        let handler = TestHandler {
+            retry_delay: Some(Duration::from_millis(1)),
+            ..TestHandler::new(vec![1; 10], Duration::from_millis(1))
+        };
+        let policy = RetryPolicy::new(3, Backoff::Fixed(Duration::from_secs(10)));
+        let queue = Queue::new(m.clone()).with_retry_policy(policy);
+        let worker = Worker::new(queue, handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        assert_eq!(handler.get_handled(), vec![1, 1, 1]);
+        assert_eq!(pending(&mut m).await, 0);
+        assert_eq!(m.dequeue(1, None).await.unwrap().is_empty(), true);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_items_with_unretryable_errors() {
+        let mut m = MemoryBuilder::new().build();
+        m.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let handler = TestHandler {
+            retry_error: Some(Arc::new(std::io::Error::other("permanent"))),
+            ..TestHandler::new(vec![1; 10], Duration::from_millis(1))
+        };
+        let policy = RetryPolicy::new(3, Backoff::Fixed(Duration::from_millis(1)))
+            .classify(|e| e.to_string() != "permanent");
+        let queue = Queue::new(m.clone()).with_retry_policy(policy);
+        let worker = Worker::new(queue, handler.clone(), options());
+        worker
+            .run(tokio::time::sleep(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        // The error was classified on the first failure
+        assert_eq!(handler.get_handled(), vec![1]);
+        assert_eq!(pending(&mut m).await, 0);
+        assert_eq!(m.dequeue(1, None).await.unwrap().is_empty(), true);
+    }
+}
@@ -0,0 +1,137 @@
+#[allow(dead_code)]
+mod util;
+
+use std::time::Duration;
+
+use rdq::queue::blocking::stream::{Stream, StreamBuilder};
+use rdq::queue::blocking::SyncQueue;
+use rdq::queue::stream::AutoclaimOptions;
+use rdq::queue::{DropOptions, JsonItem};
+
+use crate::util::with_redis;
+
+type TestQueue = SyncQueue<JsonItem<i32>, Stream<JsonItem<i32>>>;
+
+async fn with_sync_queue<F: FnOnce(TestQueue) + Send + 'static>(
+    configure: fn(StreamBuilder) -> StreamBuilder,
+    f: F,
+) {
+    with_redis(|rd_url| async move {
+        tokio::task::spawn_blocking(move || {
+            let stream = configure(StreamBuilder::new(rd_url, "s", "q")).build().unwrap();
+            f(SyncQueue::new(stream));
+        })
+        .await
+        .unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn enqueue_dequeue() {
+    with_sync_queue(|b| b, |mut queue| {
+        queue.enqueue(&JsonItem::new(1)).unwrap();
+        queue.enqueue(&JsonItem::new(2)).unwrap();
+
+        let dequeued: Vec<i32> = queue
+            .dequeue(2, None)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![1, 2]);
+
+        let dequeued = queue.dequeue(2, None).unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn blocking_dequeue_times_out() {
+    with_sync_queue(|b| b, |mut queue| {
+        let start = std::time::Instant::now();
+        let dequeued = queue.dequeue(1, Some(Duration::from_millis(200))).unwrap();
+
+        assert_eq!(dequeued.is_empty(), true);
+        assert_eq!(start.elapsed() >= Duration::from_millis(200), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn delayed_enqueue() {
+    with_sync_queue(|b| b, |mut queue| {
+        queue
+            .enqueue_in(&JsonItem::new(1), Duration::from_millis(300))
+            .unwrap();
+
+        assert_eq!(queue.dequeue(1, None).unwrap().is_empty(), true);
+
+        let dequeued = queue.dequeue(1, Some(Duration::from_secs(2))).unwrap();
+        assert_eq!(dequeued.into_iter().map(|i| i.item).collect::<Vec<_>>(), vec![1]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn delayed_enqueue_while_blocked() {
+    fn configure(builder: StreamBuilder) -> StreamBuilder {
+        builder.delayed_poll_interval(Duration::from_millis(100))
+    }
+
+    with_sync_queue(configure, |mut queue| {
+        let mut producer = queue.clone();
+        let producing = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            producer
+                .enqueue_in(&JsonItem::new(1), Duration::from_millis(100))
+                .unwrap();
+        });
+
+        // Delayed after the read started blocking, found by the poll interval
+        let start = std::time::Instant::now();
+        let dequeued = queue.dequeue(1, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(dequeued.into_iter().map(|i| i.item).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(start.elapsed() < Duration::from_secs(2), true);
+
+        producing.join().unwrap();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn autoclaims_and_acks() {
+    fn configure(builder: StreamBuilder) -> StreamBuilder {
+        builder.autoclaim_options(AutoclaimOptions {
+            frequency: 1,
+            min_idle_time: Duration::from_millis(100),
+            ..Default::default()
+        })
+    }
+
+    with_sync_queue(configure, |mut queue| {
+        queue.enqueue(&JsonItem::new(1)).unwrap();
+
+        let dequeued = queue.dequeue_deliveries(1, None).unwrap();
+        assert_eq!(dequeued.len(), 1);
+        assert_eq!(dequeued[0].deliveries, 1);
+
+        std::thread::sleep(Duration::from_millis(150));
+
+        let redelivered = queue.dequeue_deliveries(1, None).unwrap();
+        assert_eq!(redelivered.len(), 1);
+        assert_eq!(redelivered[0].deliveries, 2);
+
+        queue.ack(&[&redelivered[0].item]).unwrap();
+
+        let drop_options = DropOptions {
+            min_idle_time: Duration::ZERO,
+            max_deliveries: 0,
+            count: 100,
+            ..Default::default()
+        };
+        assert_eq!(queue.drop_items(&drop_options).unwrap().is_empty(), true);
+    })
+    .await;
+}
@@ -0,0 +1,90 @@
+use rdq::queue::memory::MemoryBuilder;
+use rdq::queue::{Backend, Error, Item};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Meta {
+    tags: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Item)]
+struct Job {
+    #[rdq(id)]
+    id: Option<String>,
+    name: String,
+    #[rdq(rename = "n")]
+    count: u32,
+    note: Option<String>,
+    #[rdq(json)]
+    meta: Meta,
+}
+
+fn job(note: Option<&str>) -> Job {
+    Job {
+        id: None,
+        name: "resize".to_string(),
+        count: 3,
+        note: note.map(|n| n.to_string()),
+        meta: Meta { tags: vec!["a".to_string(), "b".to_string()] },
+    }
+}
+
+fn stream_id(fields: &[(&str, &str)]) -> redis::streams::StreamId {
+    redis::streams::StreamId {
+        id: "1-0".to_string(),
+        map: fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), redis::Value::BulkString(v.as_bytes().to_vec())))
+            .collect(),
+    }
+}
+
+#[test]
+fn maps_fields_to_stream() {
+    let job = job(Some("urgent"));
+    let fields = job.to_stream().unwrap();
+
+    let expected = vec![
+        ("name", b"resize".to_vec()),
+        ("n", b"3".to_vec()),
+        ("note", b"urgent".to_vec()),
+        ("meta", br#"{"tags":["a","b"]}"#.to_vec()),
+    ];
+    assert_eq!(fields, expected);
+}
+
+#[test]
+fn omits_none_fields() {
+    let job = job(None);
+    let names: Vec<&str> = job.to_stream().unwrap().into_iter().map(|(k, _)| k).collect();
+
+    assert_eq!(names, vec!["name", "n", "meta"]);
+}
+
+#[test]
+fn parses_fields_from_stream() {
+    let sid = stream_id(&[("name", "resize"), ("n", "3"), ("meta", r#"{"tags":["a","b"]}"#)]);
+    let parsed = Job::from_stream(&sid).unwrap();
+
+    assert_eq!(parsed.id(), Some("1-0"));
+    assert_eq!(parsed, Job { id: Some("1-0".to_string()), ..job(None) });
+}
+
+#[test]
+fn fails_on_missing_or_invalid_fields() {
+    let sid = stream_id(&[("name", "resize"), ("meta", r#"{"tags":[]}"#)]);
+    assert_eq!(matches!(Job::from_stream(&sid), Err(Error::DeserializeError(_))), true);
+
+    let sid = stream_id(&[("name", "resize"), ("n", "three"), ("meta", r#"{"tags":[]}"#)]);
+    assert_eq!(matches!(Job::from_stream(&sid), Err(Error::DeserializeError(_))), true);
+}
+
+#[tokio::test]
+async fn round_trips_through_backend() {
+    let mut m = MemoryBuilder::new().build::<Job>();
+    m.enqueue(&job(Some("urgent"))).await.unwrap();
+
+    let dequeued = m.dequeue(1, None).await.unwrap();
+    assert_eq!(dequeued.len(), 1);
+    assert_eq!(dequeued[0].id().is_some(), true);
+    assert_eq!(Job { id: None, ..dequeued[0].clone() }, job(Some("urgent")));
+}
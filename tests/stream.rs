@@ -1,21 +1,25 @@
 mod util;
 
-use rdq::queue::stream::AutoclaimOptions;
-use rdq::queue::{DropOptions, JsonItem};
+use rdq::queue::stream::{
+    AutoclaimOptions, DeadLetterItem, ParseFailurePolicy, Retention, StartFrom, Stream,
+    StreamBuilder,
+};
+use rdq::queue::memory::MemoryBuilder;
+use rdq::queue::{
+    Backend, Backoff, DeliverySource, DropOptions, DroppedPayload, Error, Handler, JsonItem,
+    Outcome, Queue, RetryDecision, RetryPolicy, SharedQueue, Worker, WorkerOptions,
+};
 
-use crate::util::with_stream;
+use crate::util::{with_stream, with_stream_builder};
 
 #[tokio::test]
 async fn enqueue_dequeue() {
     with_stream(None, |mut queue| async move {
-        queue.enqueue(&JsonItem::new(123)).await.unwrap();
-        let dequeued: Vec<i32> = queue
-            .dequeue(1, None)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(|i| i.item)
-            .collect();
+        let id = queue.enqueue(&JsonItem::new(123)).await.unwrap();
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued[0].id, Some(id));
+
+        let dequeued: Vec<i32> = dequeued.into_iter().map(|i| i.item).collect();
         assert_eq!(dequeued, vec![123]);
     })
     .await;
@@ -75,11 +79,471 @@ async fn batch_dequeue() {
     .await;
 }
 
+#[tokio::test]
+async fn batch_enqueue() {
+    with_stream_builder(
+        |builder| builder.atomic_batches(true),
+        |mut queue| async move {
+            let ids = queue
+                .enqueue_batch(&[JsonItem::new(1), JsonItem::new(2), JsonItem::new(3)])
+                .await
+                .unwrap();
+            assert_eq!(ids.len(), 3);
+
+            let dequeued = queue.dequeue(3, None).await.unwrap();
+            let dequeued_ids: Vec<String> = dequeued.iter().map(|i| i.id.clone().unwrap()).collect();
+            assert_eq!(dequeued_ids, ids);
+            assert_eq!(dequeued.into_iter().map(|i| i.item).collect::<Vec<i32>>(), vec![1, 2, 3]);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn retention_max_len() {
+    with_stream_builder(
+        |builder| {
+            builder.retention(Retention::MaxLen {
+                len: 3,
+                approximate: false,
+            })
+        },
+        |mut queue| async move {
+            for i in 1..=5 {
+                queue.enqueue(&JsonItem::new(i)).await.unwrap();
+            }
+
+            let dequeued: Vec<i32> = queue
+                .dequeue(5, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|i| i.item)
+                .collect();
+            assert_eq!(dequeued, vec![3, 4, 5]);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn retention_applies_to_delayed_items() {
+    with_stream_builder(
+        |builder| {
+            builder.retention(Retention::MaxLen {
+                len: 2,
+                approximate: false,
+            })
+        },
+        |mut queue| async move {
+            queue
+                .enqueue_in(&JsonItem::new(1), std::time::Duration::from_millis(50))
+                .await
+                .unwrap();
+            queue.enqueue(&JsonItem::new(2)).await.unwrap();
+            queue.enqueue(&JsonItem::new(3)).await.unwrap();
+
+            std::thread::sleep(std::time::Duration::from_millis(100));
+
+            // Promoting the delayed item trims the oldest entry
+            let dequeued: Vec<i32> = queue
+                .dequeue(5, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|i| i.item)
+                .collect();
+            assert_eq!(dequeued, vec![3, 1]);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn retention_max_age() {
+    with_stream_builder(
+        |builder| {
+            builder.retention(Retention::MaxAge {
+                age: std::time::Duration::from_millis(50),
+                approximate: false,
+            })
+        },
+        |mut queue| async move {
+            queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+            std::thread::sleep(std::time::Duration::from_millis(100));
+
+            queue.enqueue(&JsonItem::new(2)).await.unwrap();
+            queue.enqueue(&JsonItem::new(3)).await.unwrap();
+
+            // Already trimmed by the XADD above
+            let trimmed = queue.backend().trim().await.unwrap();
+            assert_eq!(trimmed, 0);
+
+            let dequeued: Vec<i32> = queue
+                .dequeue(5, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|i| i.item)
+                .collect();
+            assert_eq!(dequeued, vec![2, 3]);
+
+            std::thread::sleep(std::time::Duration::from_millis(100));
+
+            let trimmed = queue.backend().trim().await.unwrap();
+            assert_eq!(trimmed, 2);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn delete_on_ack() {
+    util::with_redis(|rd_url| async move {
+        let stream = StreamBuilder::new(&rd_url, "s", "q1")
+            .delete_on_ack(true)
+            .build::<JsonItem<i32>>()
+            .await
+            .unwrap();
+        let other = StreamBuilder::new(&rd_url, "s", "q2")
+            .delete_on_ack(true)
+            .build::<JsonItem<i32>>()
+            .await
+            .unwrap();
+
+        let mut queue = Queue::new(stream);
+        let mut other = Queue::new(other);
+
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+        queue.enqueue(&JsonItem::new(2)).await.unwrap();
+
+        let mut redis = redis::Client::open(rd_url.as_str()).unwrap();
+        let len = |redis: &mut redis::Client| redis::Commands::xlen::<_, usize>(redis, "s").unwrap();
+
+        // Acked by q1, but not yet delivered to q2
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        queue.ack(&dequeued.iter().collect()).await.unwrap();
+        assert_eq!(len(&mut redis), 2);
+
+        // Delivered to q2 but only the first is acked
+        let dequeued = other.dequeue(2, None).await.unwrap();
+        other.ack(&vec![&dequeued[0]]).await.unwrap();
+        assert_eq!(len(&mut redis), 1);
+
+        other.ack(&vec![&dequeued[1]]).await.unwrap();
+        assert_eq!(len(&mut redis), 0);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn start_from() {
+    util::with_redis(|rd_url| async move {
+        let mut queue = Queue::new(
+            StreamBuilder::new(&rd_url, "s", "q1")
+                .build::<JsonItem<i32>>()
+                .await
+                .unwrap(),
+        );
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+        let cutoff = std::time::SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        queue.enqueue(&JsonItem::new(2)).await.unwrap();
+
+        let dequeue = |start_from: StartFrom, queue_name: &'static str| {
+            let rd_url = rd_url.clone();
+            async move {
+                let mut queue = Queue::new(
+                    StreamBuilder::new(rd_url, "s", queue_name)
+                        .start_from(start_from)
+                        .build::<JsonItem<i32>>()
+                        .await
+                        .unwrap(),
+                );
+                queue
+                    .dequeue(10, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|i| i.item)
+                    .collect::<Vec<i32>>()
+            }
+        };
+
+        assert_eq!(dequeue(StartFrom::Beginning, "q2").await, vec![1, 2]);
+        assert_eq!(dequeue(StartFrom::Latest, "q3").await, Vec::<i32>::new());
+        assert_eq!(dequeue(StartFrom::Timestamp(cutoff), "q4").await, vec![2]);
+
+        let first = queue.dequeue(1, None).await.unwrap();
+        let first_id = first[0].id.clone().unwrap();
+        assert_eq!(dequeue(StartFrom::Id(first_id), "q5").await, vec![2]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn group_management() {
+    with_stream(None, |mut queue| async move {
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        queue.ack(&dequeued.iter().collect()).await.unwrap();
+
+        // Replay the stream from the start
+        queue
+            .backend()
+            .set_group_position(StartFrom::Beginning)
+            .await
+            .unwrap();
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+        queue.ack(&dequeued.iter().collect()).await.unwrap();
+
+        let groups = queue.backend().groups().await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, "q".to_string());
+        assert_eq!(groups[0].consumers, 1);
+
+        // The stream's own consumer is kept
+        let deleted = queue
+            .backend()
+            .delete_idle_consumers(std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(deleted.is_empty(), true);
+
+        assert_eq!(queue.backend().destroy_group().await.unwrap(), true);
+        assert_eq!(queue.backend().groups().await.unwrap().is_empty(), true);
+        assert_eq!(queue.dequeue(1, None).await.is_err(), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn recover_pending() {
+    util::with_redis(|rd_url| async move {
+        let build = || {
+            StreamBuilder::new(&rd_url, "s", "q")
+                .consumer("c")
+                .recover_pending(true)
+                .build::<JsonItem<i32>>()
+        };
+
+        let mut queue = Queue::new(build().await.unwrap());
+        util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2), JsonItem::new(3)]).await;
+
+        // In flight when the consumer goes away
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        assert_eq!(dequeued.len(), 2);
+
+        // Restarted under the same name, the pending items come back first
+        let mut queue = Queue::new(build().await.unwrap());
+        let dequeued = queue.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued[0].item.item, 1);
+        assert_eq!(dequeued[0].source, DeliverySource::Recovered);
+        assert_eq!(dequeued[0].deliveries, 2);
+
+        let dequeued = queue.dequeue_deliveries(10, None).await.unwrap();
+        assert_eq!(dequeued.iter().map(|d| d.item.item).collect::<Vec<i32>>(), vec![2]);
+        assert_eq!(dequeued[0].source, DeliverySource::Recovered);
+
+        let dequeued = queue.dequeue_deliveries(10, None).await.unwrap();
+        assert_eq!(dequeued.iter().map(|d| d.item.item).collect::<Vec<i32>>(), vec![3]);
+        assert_eq!(dequeued[0].source, DeliverySource::Read);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn delayed_enqueue() {
+    with_stream(None, |mut queue| async move {
+        queue
+            .enqueue_in(&JsonItem::new(1), std::time::Duration::from_millis(100))
+            .await
+            .unwrap();
+        queue.enqueue(&JsonItem::new(2)).await.unwrap();
+
+        // Delayed item is not yet due
+        let dequeued: Vec<i32> = queue
+            .dequeue(2, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![2]);
+
+        // Blocking read picks the delayed item up once it becomes due
+        let started = std::time::Instant::now();
+        let dequeued: Vec<i32> = queue
+            .dequeue(2, Some(std::time::Duration::from_secs(5)))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![1]);
+        assert_eq!(started.elapsed() < std::time::Duration::from_secs(5), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn delayed_enqueue_from_another_client() {
+    util::with_redis(|rd_url| async move {
+        let build = || {
+            StreamBuilder::new(&rd_url, "s", "q")
+                .delayed_poll_interval(std::time::Duration::from_millis(100))
+                .build::<JsonItem<i32>>()
+        };
+
+        let consumer = SharedQueue::new(build().await.unwrap());
+        let mut producer = Queue::new(build().await.unwrap());
+
+        // Blocked indefinitely before the item is delayed
+        let blocked = tokio::spawn(async move {
+            consumer.dequeue(1, Some(std::time::Duration::ZERO)).await.unwrap()
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        producer
+            .enqueue_in(&JsonItem::new(1), std::time::Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        // The poll interval wakes the read to promote it
+        let dequeued = tokio::time::timeout(std::time::Duration::from_secs(2), blocked)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dequeued.into_iter().map(|i| i.item).collect::<Vec<i32>>(), vec![1]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn nack() {
+    with_stream(None, |mut queue| async move {
+        util::enqueue_all(
+            &mut queue,
+            vec![JsonItem::new(1), JsonItem::new(2), JsonItem::new(3)],
+        )
+        .await;
+
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        queue
+            .nack(&dequeued.iter().take(1).collect::<Vec<_>>(), None)
+            .await
+            .unwrap();
+        queue
+            .nack(
+                &dequeued.iter().skip(1).collect::<Vec<_>>(),
+                Some(std::time::Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+
+        // Immediately nacked item is re-added behind existing items
+        let dequeued: Vec<i32> = queue
+            .dequeue(3, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![3, 1]);
+
+        std::thread::sleep(std::time::Duration::from_millis(150));
+
+        // Delayed item comes back once due
+        let dequeued: Vec<i32> = queue
+            .dequeue(3, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![2]);
+
+        // Only the redeliveries are pending, the nacked originals were acked
+        let drop_options = DropOptions {
+            min_idle_time: std::time::Duration::from_millis(0),
+            max_deliveries: 1,
+            count: 10,
+            ..Default::default()
+        };
+        let dropped = queue.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 3);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn nack_only_pending_items() {
+    with_stream(None, |mut queue| async move {
+        util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2)]).await;
+
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        queue.ack(&vec![&dequeued[1]]).await.unwrap();
+
+        // Nacking the same delivery twice requeues it once, and acked items
+        // aren't requeued at all
+        queue.nack(&[&dequeued[0]], None).await.unwrap();
+        queue.nack(&[&dequeued[0]], None).await.unwrap();
+        queue.nack(&[&dequeued[1]], None).await.unwrap();
+
+        let dequeued: Vec<i32> = queue
+            .dequeue(5, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|i| i.item)
+            .collect();
+        assert_eq!(dequeued, vec![1]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn nacked_items_keep_their_delivery_count() {
+    with_stream_builder(
+        |builder| builder.dead_letter_stream("dlq"),
+        |mut queue| async move {
+            util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2)]).await;
+
+            // Both delivered twice, nacked in between
+            let dequeued = queue.dequeue(2, None).await.unwrap();
+            queue.nack(&[&dequeued[0], &dequeued[1]], None).await.unwrap();
+            let dequeued = queue.dequeue(2, None).await.unwrap();
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+
+            queue.dead_letter(&[&dequeued[0]]).await.unwrap();
+
+            let drop_options = DropOptions {
+                min_idle_time: std::time::Duration::from_millis(0),
+                max_deliveries: 2,
+                count: 10,
+                ..Default::default()
+            };
+            let dropped = queue.drop_items(&drop_options).await.unwrap();
+            assert_eq!(dropped.len(), 1);
+            assert_eq!(dropped[0].deliveries, 2);
+
+            let dead_letters = queue.backend_mut().dead_letters(10).await.unwrap();
+            let deliveries: Vec<u64> = dead_letters.iter().map(|i| i.deliveries).collect();
+            assert_eq!(deliveries, vec![2, 2]);
+        },
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn autoclaim_frequency() {
     let autoclaim_options = AutoclaimOptions {
         frequency: 2,
         min_idle_time: std::time::Duration::from_millis(0),
+        ..Default::default()
     };
 
     with_stream(Some(autoclaim_options), |mut queue| async move {
@@ -129,6 +593,7 @@ async fn autoclaim_idle_time() {
     let autoclaim_options = AutoclaimOptions {
         frequency: 4,
         min_idle_time: std::time::Duration::from_millis(100),
+        ..Default::default()
     };
 
     with_stream(Some(autoclaim_options), |mut queue| async move {
@@ -198,6 +663,196 @@ async fn autoclaim_idle_time() {
     .await;
 }
 
+#[tokio::test]
+async fn delivery_metadata() {
+    let autoclaim_options = AutoclaimOptions {
+        frequency: 1,
+        min_idle_time: std::time::Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    with_stream(Some(autoclaim_options), |mut queue| async move {
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        // Dequeue #1 is a read
+        let dequeued = queue.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued[0].item.item, 1);
+        assert_eq!(dequeued[0].deliveries, 1);
+        assert_eq!(dequeued[0].idle, std::time::Duration::ZERO);
+        assert_eq!(dequeued[0].source, DeliverySource::Read);
+        assert_eq!(
+            dequeued[0].enqueued_at.unwrap() <= std::time::SystemTime::now(),
+            true
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // Dequeue #2 is an autoclaim
+        let dequeued = queue.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued[0].item.item, 1);
+        assert_eq!(dequeued[0].deliveries, 2);
+        assert_eq!(dequeued[0].idle >= std::time::Duration::from_millis(50), true);
+        assert_eq!(dequeued[0].source, DeliverySource::Autoclaim);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn shared_handles_coordinate_autoclaim() {
+    let autoclaim_options = AutoclaimOptions {
+        frequency: 2,
+        min_idle_time: std::time::Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    with_stream(Some(autoclaim_options), |queue| async move {
+        let queue = SharedQueue::new(queue.backend().clone());
+        let other = queue.clone();
+
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        // Reads #1 and #2 are split across the two handles
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+        let dequeued = other.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // The autoclaim is due on either handle
+        let dequeued = queue.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+        assert_eq!(dequeued[0].source, DeliverySource::Autoclaim);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn blocking_dequeue_doesnt_hold_up_other_tasks() {
+    with_stream(None, |queue| async move {
+        let queue = SharedQueue::new(queue.backend().clone());
+        let other = queue.clone();
+
+        let blocked = tokio::spawn(async move {
+            other
+                .dequeue(1, Some(std::time::Duration::from_secs(5)))
+                .await
+                .unwrap()
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        // The enqueue isn't queued up behind the blocked read
+        let started = std::time::Instant::now();
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+        assert_eq!(started.elapsed() < std::time::Duration::from_secs(1), true);
+
+        let dequeued: Vec<i32> = blocked.await.unwrap().into_iter().map(|i| i.item).collect();
+        assert_eq!(dequeued, vec![1]);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn autoclaim_interval() {
+    let autoclaim_options = AutoclaimOptions {
+        frequency: 1,
+        min_idle_time: std::time::Duration::from_millis(0),
+        interval: Some(std::time::Duration::from_millis(200)),
+    };
+
+    with_stream(Some(autoclaim_options), |mut queue| async move {
+        queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+
+        // Not yet due, however many dequeues
+        let dequeued = queue.dequeue(1, None).await.unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+
+        // An indefinite block returns once the autoclaim is due
+        let started = std::time::Instant::now();
+        let dequeued = queue
+            .dequeue(1, Some(std::time::Duration::ZERO))
+            .await
+            .unwrap();
+        assert_eq!(dequeued.is_empty(), true);
+        assert_eq!(started.elapsed() < std::time::Duration::from_secs(1), true);
+
+        let dequeued = queue.dequeue_deliveries(1, None).await.unwrap();
+        assert_eq!(dequeued.len(), 1);
+        assert_eq!(dequeued[0].source, DeliverySource::Autoclaim);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn autoclaim_fill_batches() {
+    let autoclaim_options = AutoclaimOptions {
+        frequency: 1,
+        min_idle_time: std::time::Duration::from_millis(0),
+        ..Default::default()
+    };
+
+    with_stream_builder(
+        |builder| builder.autoclaim_options(autoclaim_options).fill_batches(true),
+        |mut queue| async move {
+            queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+            let dequeued = queue.dequeue(1, None).await.unwrap();
+            assert_eq!(dequeued.len(), 1);
+
+            queue.enqueue(&JsonItem::new(2)).await.unwrap();
+            queue.enqueue(&JsonItem::new(3)).await.unwrap();
+
+            // The reclaimed item is topped up with new ones
+            let dequeued = queue.dequeue_deliveries(3, None).await.unwrap();
+            let items: Vec<i32> = dequeued.iter().map(|d| d.item.item).collect();
+            assert_eq!(items, vec![1, 2, 3]);
+            assert_eq!(dequeued[0].source, DeliverySource::Autoclaim);
+            assert_eq!(dequeued[1].source, DeliverySource::Read);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn autoclaim_deleted_entries() {
+    util::with_redis(|rd_url| async move {
+        let deleted = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let reported = deleted.clone();
+
+        let stream = StreamBuilder::new(&rd_url, "s", "q")
+            .autoclaim_options(AutoclaimOptions {
+                frequency: 1,
+                min_idle_time: std::time::Duration::from_millis(0),
+                ..Default::default()
+            })
+            .on_deleted(move |ids| reported.lock().unwrap().extend_from_slice(ids))
+            .build::<JsonItem<i32>>()
+            .await
+            .unwrap();
+        let mut queue = Queue::new(stream);
+
+        util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2)]).await;
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        let first_id = dequeued[0].id.clone().unwrap();
+
+        // Deleted while pending, e.g. by trimming
+        let mut redis = redis::Client::open(rd_url.as_str()).unwrap();
+        let _: usize = redis::Commands::xdel(&mut redis, "s", &[&first_id]).unwrap();
+
+        let dequeued = queue.dequeue(2, None).await.unwrap();
+        assert_eq!(dequeued.iter().map(|i| i.item).collect::<Vec<i32>>(), vec![2]);
+        assert_eq!(*deleted.lock().unwrap(), vec![first_id]);
+
+        let pending: redis::streams::StreamPendingReply =
+            redis::Commands::xpending(&mut redis, "s", "q").unwrap();
+        assert_eq!(pending.count(), 1);
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn drop_items() {
     with_stream(None, |mut queue| async move {
@@ -228,6 +883,8 @@ async fn drop_items() {
             min_idle_time: std::time::Duration::from_millis(50),
             max_deliveries: 1,
             count: 2,
+            scan_limit: Some(2),
+            ..Default::default()
         };
 
         // Nothing will be dropped because no pending items exceed min idle time
@@ -250,6 +907,7 @@ async fn drop_items() {
             min_idle_time: std::time::Duration::from_millis(50),
             max_deliveries: 2,
             count: 2,
+            ..Default::default()
         };
 
         // Nothing will be dropped because nothing is at max deliveries
@@ -260,6 +918,7 @@ async fn drop_items() {
             min_idle_time: std::time::Duration::from_millis(50),
             max_deliveries: 1,
             count: 2,
+            ..Default::default()
         };
 
         // Third enqueued item will be dropped because it is at max deliveries
@@ -274,3 +933,445 @@ async fn drop_items() {
     })
     .await;
 }
+
+#[tokio::test]
+async fn drop_items_paginated() {
+    util::with_redis(|rd_url| async move {
+        let build = |consumer: &'static str| {
+            StreamBuilder::new(&rd_url, "s", "q")
+                .consumer(consumer)
+                .build::<JsonItem<i32>>()
+        };
+
+        let mut queue = Queue::new(build("c1").await.unwrap());
+        let mut other = Queue::new(build("c2").await.unwrap());
+
+        util::enqueue_all(&mut queue, (1..=10).map(JsonItem::new).collect()).await;
+        queue.dequeue(5, None).await.unwrap();
+        other.dequeue(5, None).await.unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let drop_options = DropOptions {
+            min_idle_time: std::time::Duration::from_millis(0),
+            max_deliveries: 1,
+            count: 2,
+            scan_limit: Some(3),
+            consumer: Some("c2".to_string()),
+            ..Default::default()
+        };
+
+        // Only the first three of c2's items are inspected
+        let dropped = queue.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 3);
+
+        // Every page of the remaining pending items is scanned
+        let drop_options = DropOptions {
+            scan_limit: None,
+            consumer: None,
+            ..drop_options
+        };
+        let dropped = queue.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 7);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn drop_items_payloads() {
+    with_malformed_entry(ParseFailurePolicy::Collect, |mut queue, _| async move {
+        let dequeued = queue.dequeue(3, None).await.unwrap();
+        assert_eq!(dequeued.len(), 2);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let drop_options = DropOptions {
+            min_idle_time: std::time::Duration::from_millis(0),
+            max_deliveries: 1,
+            count: 10,
+            payloads: true,
+            ..Default::default()
+        };
+        let dropped = queue.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 3);
+
+        let items: Vec<Option<i32>> = dropped
+            .iter()
+            .map(|d| match &d.payload {
+                Some(DroppedPayload::Item(i)) => Some(i.item),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(items, vec![Some(1), None, Some(2)]);
+
+        // The malformed entry comes back as raw fields
+        let Some(DroppedPayload::Raw(fields)) = &dropped[1].payload else {
+            panic!("expected raw fields");
+        };
+        assert_eq!(fields.contains_key("json"), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn worker_dead_letters_failed_items() {
+    struct FailOdd;
+
+    #[async_trait::async_trait]
+    impl Handler<JsonItem<i32>> for FailOdd {
+        async fn handle(&self, item: &JsonItem<i32>) -> Outcome {
+            if item.item % 2 == 1 {
+                Outcome::Fail
+            } else {
+                Outcome::Success
+            }
+        }
+    }
+
+    with_stream_builder(
+        |builder| builder.dead_letter_stream("dlq"),
+        |mut queue| async move {
+            util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2), JsonItem::new(3)]).await;
+
+            let options = WorkerOptions {
+                poll_timeout: std::time::Duration::from_millis(10),
+                ..Default::default()
+            };
+            Worker::new(queue.clone(), FailOdd, options)
+                .run(tokio::time::sleep(std::time::Duration::from_millis(200)))
+                .await
+                .unwrap();
+
+            let dead_letters = queue.backend_mut().dead_letters(10).await.unwrap();
+            let items: Vec<i32> = dead_letters
+                .iter()
+                .map(|i| i.item.as_ref().unwrap().item)
+                .collect();
+            assert_eq!(items, vec![1, 3]);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn drop_items_into_dead_letter_stream() {
+    with_stream_builder(
+        |builder| builder.dead_letter_stream("dlq"),
+        |mut queue| async move {
+            util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2)]).await;
+
+            // Dequeued but not acked, will be dead-lettered
+            let dequeued = queue.dequeue(2, None).await.unwrap();
+
+            let drop_options = DropOptions {
+                min_idle_time: std::time::Duration::from_millis(0),
+                max_deliveries: 1,
+                count: 2,
+                ..Default::default()
+            };
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+
+            let dropped = queue.drop_items(&drop_options).await.unwrap();
+            assert_eq!(dropped.len(), 2);
+
+            let dead_letters = queue.backend_mut().dead_letters(10).await.unwrap();
+            let items: Vec<i32> = dead_letters
+                .iter()
+                .map(|i| i.item.as_ref().unwrap().item)
+                .collect();
+            assert_eq!(items, vec![1, 2]);
+
+            let source_ids: Vec<String> =
+                dead_letters.iter().map(|i| i.source_id.clone()).collect();
+            let dequeued_ids: Vec<String> =
+                dequeued.iter().map(|i| i.id.clone().unwrap()).collect();
+            assert_eq!(source_ids, dequeued_ids);
+            assert_eq!(dead_letters[0].group, "q".to_string());
+            assert_eq!(dead_letters[0].deliveries, 1);
+
+            // Nothing left pending
+            let dropped = queue.drop_items(&drop_options).await.unwrap();
+            assert_eq!(dropped.is_empty(), true);
+
+            // Requeue the first dead letter back into the stream
+            let requeued = queue
+                .backend_mut()
+                .requeue_dead_letters(&[dead_letters[0].id.as_str()])
+                .await
+                .unwrap();
+            assert_eq!(requeued, 1);
+
+            let dead_letters = queue.backend_mut().dead_letters(10).await.unwrap();
+            assert_eq!(dead_letters.len(), 1);
+
+            let dequeued: Vec<i32> = queue
+                .dequeue(2, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|i| i.item)
+                .collect();
+            assert_eq!(dequeued, vec![1]);
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn drop_items_into_dead_letter_backend() {
+    let dead_letter = MemoryBuilder::new().build::<DeadLetterItem<JsonItem<i32>>>();
+
+    with_stream_builder(
+        |builder| builder.dead_letter_backend(dead_letter.clone()),
+        |mut queue| {
+            let mut dead_letter = dead_letter.clone();
+
+            async move {
+                util::enqueue_all(&mut queue, vec![JsonItem::new(1), JsonItem::new(2)]).await;
+                let dequeued = queue.dequeue(2, None).await.unwrap();
+
+                let drop_options = DropOptions {
+                    min_idle_time: std::time::Duration::from_millis(0),
+                    max_deliveries: 1,
+                    count: 2,
+                    ..Default::default()
+                };
+
+                std::thread::sleep(std::time::Duration::from_millis(10));
+
+                let dropped = queue.drop_items(&drop_options).await.unwrap();
+                assert_eq!(dropped.len(), 2);
+
+                let dead_letters = dead_letter.dequeue(2, None).await.unwrap();
+                let items: Vec<i32> = dead_letters
+                    .iter()
+                    .map(|i| i.item.as_ref().unwrap().item)
+                    .collect();
+                assert_eq!(items, vec![1, 2]);
+
+                // Dead letters carry their delivery metadata
+                assert_eq!(dead_letters[0].source_stream, "s".to_string());
+                assert_eq!(dead_letters[0].source_id, dequeued[0].id.clone().unwrap());
+                assert_eq!(dead_letters[0].group, "q".to_string());
+                assert_eq!(dead_letters[0].deliveries, 1);
+
+                // Nothing left pending
+                let dropped = queue.drop_items(&drop_options).await.unwrap();
+                assert_eq!(dropped.is_empty(), true);
+            }
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn drop_malformed_items_into_dead_letter_backend() {
+    let dead_letter = MemoryBuilder::new().build::<DeadLetterItem<JsonItem<i32>>>();
+
+    util::with_redis(|rd_url| async move {
+        // A producer writing strings into a stream consumed as integers
+        let producer = StreamBuilder::new(&rd_url, "s", "q").build().await.unwrap();
+        let mut producer = Queue::new(producer);
+
+        let consumer = StreamBuilder::new(&rd_url, "s", "q")
+            .dead_letter_backend(dead_letter.clone())
+            .build()
+            .await
+            .unwrap();
+        let mut consumer: Queue<JsonItem<i32>, Stream<JsonItem<i32>>> = Queue::new(consumer);
+
+        consumer.enqueue(&JsonItem::new(1)).await.unwrap();
+        producer
+            .enqueue(&JsonItem::new("abc".to_string()))
+            .await
+            .unwrap();
+
+        // The malformed entry fails the dequeue, leaving both entries pending
+        assert_eq!(consumer.dequeue(2, None).await.is_err(), true);
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        let drop_options = DropOptions {
+            min_idle_time: std::time::Duration::from_millis(0),
+            max_deliveries: 1,
+            count: 10,
+            ..Default::default()
+        };
+        let dropped = consumer.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.len(), 2);
+
+        // The malformed entry is moved with its raw fields
+        let dead_letters = dead_letter.clone().dequeue(2, None).await.unwrap();
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!(dead_letters[0].item.as_ref().unwrap().item, 1);
+        assert_eq!(dead_letters[1].item.is_none(), true);
+        assert_eq!(dead_letters[1].fields.contains_key("json"), true);
+
+        // Nothing left pending
+        let dropped = consumer.drop_items(&drop_options).await.unwrap();
+        assert_eq!(dropped.is_empty(), true);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn retry_policy() {
+    with_stream_builder(
+        |builder| builder.dead_letter_stream("dlq"),
+        |queue| async move {
+            let policy = RetryPolicy::new(3, Backoff::Exponential {
+                initial: std::time::Duration::from_millis(10),
+                factor: 2.0,
+            });
+            let mut queue = queue.with_retry_policy(policy);
+            queue.enqueue(&JsonItem::new(1)).await.unwrap();
+
+            // Deliveries carry over nacks, with growing delays in between
+            for (deliveries, delay) in [(1, 10), (2, 20)] {
+                let dequeued = queue
+                    .dequeue_deliveries(1, Some(std::time::Duration::from_secs(5)))
+                    .await
+                    .unwrap();
+                assert_eq!(dequeued[0].deliveries, deliveries);
+
+                let decision = queue.retry(&dequeued[0], None).await.unwrap();
+                assert_eq!(
+                    decision,
+                    RetryDecision::Retry(std::time::Duration::from_millis(delay))
+                );
+            }
+
+            // Attempts exhausted, the item is dead-lettered
+            let dequeued = queue
+                .dequeue_deliveries(1, Some(std::time::Duration::from_secs(5)))
+                .await
+                .unwrap();
+            assert_eq!(dequeued[0].deliveries, 3);
+            let decision = queue.retry(&dequeued[0], None).await.unwrap();
+            assert_eq!(decision, RetryDecision::DeadLetter);
+
+            let dead_letters = queue.backend_mut().dead_letters(10).await.unwrap();
+            assert_eq!(dead_letters.len(), 1);
+            assert_eq!(dead_letters[0].item.as_ref().unwrap().item, 1);
+
+            let dequeued = queue.dequeue(1, None).await.unwrap();
+            assert_eq!(dequeued.is_empty(), true);
+        },
+    )
+    .await;
+}
+
+async fn with_malformed_entry<
+    F: FnOnce(Queue<JsonItem<i32>, Stream<JsonItem<i32>>>, String) -> Fut,
+    Fut: Future<Output = ()>,
+>(
+    policy: ParseFailurePolicy,
+    f: F,
+) {
+    util::with_redis(|rd_url| async move {
+        // A producer writing strings into a stream consumed as integers
+        let producer = StreamBuilder::new(&rd_url, "s", "q").build().await.unwrap();
+        let mut producer = Queue::new(producer);
+
+        let consumer = StreamBuilder::new(&rd_url, "s", "q")
+            .parse_failure_policy(policy)
+            .build()
+            .await
+            .unwrap();
+        let mut consumer = Queue::new(consumer);
+
+        consumer.enqueue(&JsonItem::new(1)).await.unwrap();
+        producer
+            .enqueue(&JsonItem::new("abc".to_string()))
+            .await
+            .unwrap();
+        consumer.enqueue(&JsonItem::new(2)).await.unwrap();
+
+        f(consumer, rd_url).await;
+    })
+    .await;
+}
+
+/// Ack everything still pending, returning how many items that was.
+async fn drain_pending(queue: &mut Queue<JsonItem<i32>, Stream<JsonItem<i32>>>) -> usize {
+    std::thread::sleep(std::time::Duration::from_millis(10));
+
+    let drop_options = DropOptions {
+        min_idle_time: std::time::Duration::from_millis(0),
+        max_deliveries: 0,
+        count: 100,
+        ..Default::default()
+    };
+
+    queue.drop_items(&drop_options).await.unwrap().len()
+}
+
+#[tokio::test]
+async fn parse_failure_error() {
+    with_malformed_entry(ParseFailurePolicy::Error, |mut queue, _| async move {
+        let res = queue.dequeue(3, None).await;
+        assert_eq!(
+            matches!(res, Err(Error::ParseError(_, e)) if matches!(*e, Error::DeserializeError(_))),
+            true
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn parse_failure_skip() {
+    with_malformed_entry(ParseFailurePolicy::Skip, |mut queue, _| async move {
+        let dequeued = queue.dequeue(3, None).await.unwrap();
+        queue.ack(&dequeued.iter().collect()).await.unwrap();
+        let dequeued: Vec<i32> = dequeued.into_iter().map(|i| i.item).collect();
+        assert_eq!(dequeued, vec![1, 2]);
+
+        assert_eq!(drain_pending(&mut queue).await, 0);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn parse_failure_quarantine() {
+    with_malformed_entry(
+        ParseFailurePolicy::Quarantine("quarantine".to_string()),
+        |mut queue, rd_url| async move {
+            let dequeued = queue.dequeue(3, None).await.unwrap();
+            queue.ack(&dequeued.iter().collect()).await.unwrap();
+            let dequeued: Vec<i32> = dequeued.into_iter().map(|i| i.item).collect();
+            assert_eq!(dequeued, vec![1, 2]);
+
+            assert_eq!(drain_pending(&mut queue).await, 0);
+
+            // The quarantine stream has the same layout as a dead-letter stream
+            let quarantine: Stream<JsonItem<String>> = StreamBuilder::new(rd_url, "s", "q")
+                .dead_letter_stream("quarantine")
+                .build()
+                .await
+                .unwrap();
+            let quarantined = quarantine.dead_letters(10).await.unwrap();
+            assert_eq!(quarantined.len(), 1);
+            assert_eq!(quarantined[0].item.as_ref().unwrap().item, "abc".to_string());
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn parse_failure_collect() {
+    with_malformed_entry(ParseFailurePolicy::Collect, |mut queue, _| async move {
+        let dequeued = queue.dequeue(3, None).await.unwrap();
+        queue.ack(&dequeued.iter().collect()).await.unwrap();
+        let dequeued: Vec<i32> = dequeued.into_iter().map(|i| i.item).collect();
+        assert_eq!(dequeued, vec![1, 2]);
+
+        let failures = queue.backend_mut().take_parse_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(queue.backend_mut().take_parse_failures().is_empty(), true);
+
+        // The malformed entry is left pending
+        assert_eq!(drain_pending(&mut queue).await, 1);
+    })
+    .await;
+}
@@ -12,27 +12,48 @@ pub async fn with_stream<
     autoclaim_options: Option<AutoclaimOptions>,
     f: F,
 ) {
+    with_stream_builder(
+        |builder| match autoclaim_options {
+            Some(options) => builder.autoclaim_options(options),
+            None => builder,
+        },
+        f,
+    )
+    .await;
+}
+
+pub async fn with_stream_builder<
+    I: Item + Send + Sync,
+    C: FnOnce(StreamBuilder) -> StreamBuilder,
+    F: Fn(Queue<I, Stream<I>>) -> Fut,
+    Fut: Future<Output = ()>,
+>(
+    configure: C,
+    f: F,
+) {
+    with_redis(|rd_url| async move {
+        let builder = configure(StreamBuilder::new(rd_url, "s", "q"));
+
+        let stream = builder.build().await.unwrap();
+        let queue = Queue::new(stream);
+
+        f(queue).await;
+    })
+    .await;
+}
+
+pub async fn with_redis<F: FnOnce(String) -> Fut, Fut: Future<Output = ()>>(f: F) {
     let rd = Redis::default().with_tag("alpine").start().await.unwrap();
     tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
     let rd_port = rd.get_host_port_ipv4(6379).await.unwrap();
     let rd_url = format!("redis://127.0.0.1:{rd_port}");
 
-    let mut builder = StreamBuilder::new(rd_url, "s", "q");
-    if let Some(options) = autoclaim_options {
-        builder = builder.autoclaim_options(options);
-    }
-
-    let stream = builder.build().await.unwrap();
-    let queue = Queue::new(stream);
-
-    f(queue).await;
+    f(rd_url).await;
 }
 
 pub async fn enqueue_all<I: Item + Send + Sync, B: Backend<I>>(
     queue: &mut Queue<I, B>,
     items: Vec<I>,
 ) {
-    for item in items.iter() {
-        queue.enqueue(&item).await.unwrap();
-    }
+    queue.enqueue_batch(&items).await.unwrap();
 }